@@ -2,95 +2,1581 @@
 // AI Commit Message Generation
 // ===================================================================
 
-use reqwest::Client;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::error::AiCommitError;
+use crate::providers::{
+    Alternative, ApiProvider, ApiStyle, ChatRequest, ChatResponse, HttpSettings, Provider,
+    format_wait,
+};
+use crate::structured::{self, CommitParts};
+use crate::template;
+use crate::{output, progress};
+
+/// Default delay before the first retry; doubled for each further attempt.
+pub const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Default longest wait a rate limit may ask for before giving up.
+pub const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+/// Lines kept from the start of each hunk when a diff has to be truncated;
+/// fewer are kept if the result would still be too large.
+const MAX_HUNK_LINES: usize = 20;
+
+/// OpenAI accepts at most this many stop sequences.
+pub const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Marks where part of a diff was left out.
+const TRUNCATION_MARKER: &str = "[…truncated…]";
+
+/// Shown when a reply fails the format check and is asked for again.
+const FORMAT_RETRY_WARNING: &str = "Message does not follow the requested format, retrying...";
+
+/// How many times a request rejected as too large is retried with a diff
+/// half the size.
+const MAX_SHRINKS: u32 = 2;
+
+/// Longest subject line asked for, and kept with `--subject-only`.
+pub const MAX_SUBJECT_CHARS: usize = 72;
 
-#[derive(Serialize)]
-struct OpenAiRequest {
-    model: String,
-    messages: Vec<Message>,
+/// Gitmoji offered to the model, from https://gitmoji.dev.
+const GITMOJI: &[(&str, &str)] = &[
+    ("✨", "new feature"),
+    ("🐛", "bug fix"),
+    ("🚑️", "critical hotfix"),
+    ("♻️", "refactor"),
+    ("⚡️", "performance"),
+    ("📝", "documentation"),
+    ("🎨", "code structure or formatting"),
+    ("🔥", "remove code or files"),
+    ("✅", "tests"),
+    ("🔒️", "security"),
+    ("⬆️", "upgrade dependencies"),
+    ("⬇️", "downgrade dependencies"),
+    ("🔧", "configuration"),
+    ("👷", "CI"),
+    ("💄", "UI and styles"),
+    ("🌐", "internationalization"),
+    ("🚚", "move or rename"),
+    ("🏷️", "types"),
+    ("🗃️", "database"),
+    ("⏪️", "revert"),
+    ("🎉", "begin a project"),
+];
+
+fn gitmoji_instructions() -> String {
+    let list: Vec<String> = GITMOJI
+        .iter()
+        .map(|(emoji, meaning)| format!("{} {}", emoji, meaning))
+        .collect();
+    format!(
+        " Start the subject line with the single most fitting gitmoji, followed by a space. \
+        Choose from: {}.",
+        list.join(", ")
+    )
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Message {
-    role: String,
-    content: String,
+/// `message` without its leading emoji, or `None` if it doesn't start with one.
+fn strip_emoji(message: &str) -> Option<&str> {
+    let is_emoji = |c: char| matches!(c as u32, 0x2190..=0x21FF | 0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x1F000..=0x1FAFF);
+    if !message.chars().next().is_some_and(is_emoji) {
+        return None;
+    }
+    // Variation selectors and zero-width joiners belong to the emoji.
+    let rest =
+        message.trim_start_matches(|c: char| is_emoji(c) || matches!(c, '\u{FE0F}' | '\u{200D}'));
+    Some(rest.trim_start())
 }
 
-#[derive(Deserialize, Debug)]
-struct OpenAiResponse {
-    choices: Vec<Choice>,
+/// Matches a Conventional Commits subject line, e.g. `feat(cli): add --format`.
+static CONVENTIONAL_SUBJECT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(feat|fix|docs|refactor|test|chore|perf|ci)(\([\w./-]+\))?!?: \S.{0,71}$")
+        .unwrap()
+});
+
+/// The shape the generated message should take.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommitFormat {
+    /// Whatever the model produces.
+    #[default]
+    Free,
+    /// `type(scope): subject`, per https://www.conventionalcommits.org.
+    Conventional,
 }
 
-#[derive(Deserialize, Debug)]
-struct Choice {
-    message: Message,
+impl CommitFormat {
+    pub fn from_name(name: &str) -> Option<CommitFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "free" => Some(CommitFormat::Free),
+            "conventional" => Some(CommitFormat::Conventional),
+            _ => None,
+        }
+    }
+
+    fn instructions(self) -> &'static str {
+        match self {
+            CommitFormat::Free => "",
+            CommitFormat::Conventional => {
+                " Follow the Conventional Commits format: start the subject line with a type \
+                (feat, fix, docs, refactor, test, chore, perf or ci), optionally followed by a scope \
+                in parentheses, then a colon, a space and a subject of at most 72 characters, \
+                e.g. `fix(parser): handle empty input`."
+            }
+        }
+    }
+
+    /// Whether `message` follows this format. Only the subject line is checked.
+    pub fn accepts(self, message: &str) -> bool {
+        match self {
+            CommitFormat::Free => true,
+            CommitFormat::Conventional => {
+                let subject = message.trim().lines().next().unwrap_or_default();
+                CONVENTIONAL_SUBJECT.is_match(subject)
+            }
+        }
+    }
 }
 
-pub async fn generate_commit_message(
-    diff: &str,
-    api_key: &str,
-    language: &str,
-    prompt: &str,
-    url: &str,
-    model: &str,
-) -> Result<String, String> {
-    let client = Client::new();
-
-    let system_prompt = format!(
-        "You are a helpful assistant that generates commit messages in {}. \
-        The user will provide a git diff, and you should generate a concise and informative commit message. {}",
-        language, prompt
-    );
-
-    let user_prompt = format!("Here is the git diff:\n```\n{}\n```", diff);
-
-    let request = OpenAiRequest {
-        model: model.to_string(),
-        messages: vec![
-            Message {
-                role: "system".to_string(),
-                content: system_prompt,
+/// Token counts reported by the API for one request.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl TokenUsage {
+    /// Adds up the usage of several requests, or `None` if none reported any.
+    pub fn sum(usages: impl IntoIterator<Item = Option<TokenUsage>>) -> Option<TokenUsage> {
+        usages
+            .into_iter()
+            .flatten()
+            .reduce(|total, usage| TokenUsage {
+                prompt_tokens: total.prompt_tokens + usage.prompt_tokens,
+                completion_tokens: total.completion_tokens + usage.completion_tokens,
+                total_tokens: total.total_tokens + usage.total_tokens,
+            })
+    }
+}
+
+impl fmt::Display for TokenUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} prompt + {} completion = {} total",
+            self.prompt_tokens, self.completion_tokens, self.total_tokens
+        )
+    }
+}
+
+/// A generated message and what produced it.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub message: String,
+    /// The model that answered, which may be a fallback model.
+    pub model: String,
+    pub usage: Option<TokenUsage>,
+    /// Whether files were left out of the diff because the API rejected it
+    /// as too large.
+    pub truncated: bool,
+    /// Whether the reply stopped at the token limit.
+    pub cut_off: bool,
+    /// Wall-clock time of the request, including retries. `None` for a
+    /// cached message.
+    pub latency: Option<Duration>,
+}
+
+impl Generation {
+    fn new(opts: &GenerationOptions, response: &ChatResponse, model: String) -> Generation {
+        log_fingerprint(opts, response);
+        Generation {
+            message: opts.render(&response.content),
+            model,
+            usage: response.usage,
+            truncated: false,
+            cut_off: response.cut_off,
+            latency: None,
+        }
+    }
+}
+
+/// Logs the backend fingerprint of a seeded reply, which tells whether it
+/// can be repeated.
+fn log_fingerprint(opts: &GenerationOptions, response: &ChatResponse) {
+    if opts.seed.is_some()
+        && let Some(fingerprint) = &response.system_fingerprint
+    {
+        debug!("System fingerprint: {}", fingerprint);
+    }
+}
+
+/// `content` up to the earliest of the `stop` strings, for backends that
+/// ignore them.
+pub fn cut_at_stop<'a>(content: &'a str, stop: &[String]) -> &'a str {
+    stop.iter()
+        .filter(|stop| !stop.is_empty())
+        .filter_map(|stop| content.find(stop.as_str()))
+        .min()
+        .map_or(content, |end| content[..end].trim_end())
+}
+
+/// How much of a commit message to produce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageShape {
+    /// Whatever the model produces.
+    #[default]
+    Any,
+    /// A single subject line of at most [`MAX_SUBJECT_CHARS`].
+    SubjectOnly,
+    /// A subject line, a blank line, then a body explaining why.
+    WithBody,
+}
+
+impl MessageShape {
+    fn instructions(self) -> &'static str {
+        match self {
+            MessageShape::Any => "",
+            MessageShape::SubjectOnly => {
+                " Output only a single subject line of at most 72 characters. \
+                Do not include a body or bullet points."
+            }
+            MessageShape::WithBody => {
+                " Start with a subject line of at most 72 characters, then a blank line, \
+                then a body of one or more paragraphs explaining why the change was made."
+            }
+        }
+    }
+
+    /// Cuts `message` down to this shape, for models that ignore the
+    /// instructions.
+    pub fn apply(self, message: &str) -> String {
+        match self {
+            MessageShape::Any => message.to_string(),
+            _ => self.prefix(message).trim_end().to_string(),
+        }
+    }
+
+    /// What [`apply`](Self::apply) keeps of a message that is still
+    /// streaming in. Only ever grows as more text arrives, so it can be
+    /// printed piece by piece.
+    fn prefix(self, partial: &str) -> String {
+        match self {
+            MessageShape::Any => partial.to_string(),
+            MessageShape::SubjectOnly => partial
+                .trim_start()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .take(MAX_SUBJECT_CHARS)
+                .collect(),
+            MessageShape::WithBody => {
+                let partial = partial.trim_start();
+                match partial.split_once('\n') {
+                    Some((subject, body)) => {
+                        format!("{}\n\n{}", subject.trim_end(), body.trim_start())
+                    }
+                    // Trailing whitespace is dropped once the newline arrives,
+                    // so it can't be printed before then.
+                    None => partial.trim_end().to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// What can be shown of `content` while it is still streaming in: up to the
+/// first `stop` string, and short of an ending that may be the start of one.
+fn before_stop<'a>(content: &'a str, stop: &[String]) -> &'a str {
+    let stop = stop.iter().filter(|stop| !stop.is_empty());
+    if let Some(end) = stop.clone().filter_map(|s| content.find(s.as_str())).min() {
+        return &content[..end];
+    }
+    let held = stop
+        .flat_map(|stop| {
+            (1..stop.len())
+                .filter(|&len| stop.is_char_boundary(len))
+                .map(|len| &stop[..len])
+        })
+        .filter(|start| content.ends_with(start))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    &content[..content.len() - held]
+}
+
+/// Passes on only the part of a streamed message that survives its
+/// [`MessageShape`] and `stop` strings.
+struct ShapedStream<'a> {
+    shape: MessageShape,
+    stop: &'a [String],
+    on_delta: &'a mut (dyn FnMut(&str) + Send),
+    received: String,
+    passed_on: usize,
+}
+
+impl<'a> ShapedStream<'a> {
+    fn new(
+        shape: MessageShape,
+        stop: &'a [String],
+        on_delta: &'a mut (dyn FnMut(&str) + Send),
+    ) -> Self {
+        ShapedStream {
+            shape,
+            stop,
+            on_delta,
+            received: String::new(),
+            passed_on: 0,
+        }
+    }
+
+    fn push(&mut self, delta: &str) {
+        self.received.push_str(delta);
+        let kept = self.shape.prefix(before_stop(&self.received, self.stop));
+        self.pass_on(&kept);
+    }
+
+    /// Passes on what was held back in case it began a stop string.
+    fn finish(&mut self) {
+        let kept = self.shape.prefix(cut_at_stop(&self.received, self.stop));
+        self.pass_on(&kept);
+    }
+
+    fn pass_on(&mut self, kept: &str) {
+        if let Some(new) = kept.get(self.passed_on..).filter(|new| !new.is_empty()) {
+            (self.on_delta)(new);
+        }
+        self.passed_on = self.passed_on.max(kept.len());
+    }
+}
+
+/// Everything a backend needs to turn a diff into a commit message.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationOptions {
+    pub api_key: String,
+    pub language: String,
+    pub prompt: String,
+    pub url: String,
+    pub model: String,
+    /// Azure deployment name; falls back to the model name.
+    pub azure_deployment: Option<String>,
+    pub azure_api_version: Option<String>,
+    /// AWS region used to sign Bedrock requests.
+    pub region: Option<String>,
+    /// Google Cloud project for Vertex AI.
+    pub project: Option<String>,
+    /// Stream the reply as it is generated, where the provider supports it.
+    pub stream: bool,
+    pub format: CommitFormat,
+    /// Ask for the message as JSON parts and assemble it here.
+    pub structured: bool,
+    /// Start the subject line with a gitmoji.
+    pub gitmoji: bool,
+    /// How many times to retry rate-limited or failed (5xx) requests.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub retry_delay: Duration,
+    /// Rate limits that ask for a longer wait than this fail straight away.
+    pub max_retry_wait: Duration,
+    /// Chat or legacy text completions, for OpenAI-compatible endpoints.
+    pub api_style: ApiStyle,
+    /// Forces reasoning-model compatibility on or off; detected from the
+    /// model name when unset.
+    pub reasoning_model: Option<bool>,
+    /// Models tried in order when the primary one keeps failing with a
+    /// transient error.
+    pub fallback_models: Vec<String>,
+    /// Further API keys for the same endpoint, tried in turn when a request
+    /// is rate limited.
+    pub api_keys: Vec<String>,
+    /// Extra HTTP headers sent with each request.
+    pub headers: BTreeMap<String, String>,
+    pub http: HttpSettings,
+    /// Sampling temperature; the API default applies when unset.
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    /// Seed for repeatable sampling, left out for backends that reject it.
+    pub seed: Option<u64>,
+    /// Strings the reply is cut off at, by the API where it supports them
+    /// and again here.
+    pub stop: Vec<String>,
+    /// Upper bound on tokens in the reply; each backend has its own default.
+    pub max_tokens: Option<u32>,
+    /// The model's context window in tokens, if configured rather than
+    /// looked up.
+    pub context_window: Option<usize>,
+    /// Prompt template for raw-completion servers such as llama.cpp, with
+    /// `{system}` and `{user}` placeholders.
+    pub chat_template: Option<String>,
+    /// User message with `{diff}`, `{language}`, `{branch}`, `{files}` and
+    /// `{stat}` placeholders, instead of the built-in one.
+    pub prompt_template: Option<String>,
+    /// The current branch, for `{branch}`.
+    pub branch: Option<String>,
+    /// Look up the branch, repository name and recent commits.
+    pub include_context: bool,
+    /// Name only the current branch, e.g. for a ticket number in it.
+    pub include_branch: bool,
+    /// What was found, added to the system prompt.
+    pub repo_context: Option<String>,
+    /// Start the user message with a one-line summary of the diff.
+    pub stat_prefix: bool,
+    /// That summary, e.g. `3 files changed, 42 insertions(+), 7 deletions(-)`.
+    pub diff_stat: Option<String>,
+    /// Message of the commit being amended, for the model to improve on.
+    pub previous_message: Option<String>,
+    /// Whether to ask for a subject line only, or a subject and body.
+    pub shape: MessageShape,
+}
+
+impl GenerationOptions {
+    fn system_prompt(&self) -> String {
+        let prompt = format!(
+            "You are a helpful assistant that generates commit messages in {}. \
+            The user will provide a git diff, and you should generate a concise and informative commit message.{}{}{} {}",
+            self.language,
+            if self.structured {
+                structured::INSTRUCTIONS
+            } else {
+                self.format.instructions()
             },
-            Message {
-                role: "user".to_string(),
-                content: user_prompt,
+            self.shape.instructions(),
+            if self.gitmoji {
+                gitmoji_instructions()
+            } else {
+                String::new()
+            },
+            self.prompt
+        );
+        match &self.repo_context {
+            Some(context) => format!("{}\n\n{}", prompt, context),
+            None => prompt,
+        }
+    }
+
+    /// Whether replies are checked with [`Self::accepts`] and may be retried.
+    fn checks_reply(&self) -> bool {
+        self.structured || self.gitmoji || self.format != CommitFormat::Free
+    }
+
+    /// Whether `message` follows the requested format and, with `gitmoji`,
+    /// starts with an emoji.
+    fn accepts(&self, message: &str) -> bool {
+        if self.structured {
+            return CommitParts::parse(message).is_some();
+        }
+        if !self.gitmoji {
+            return self.format.accepts(message);
+        }
+        strip_emoji(message.trim_start()).is_some_and(|rest| self.format.accepts(rest))
+    }
+
+    /// Turns a reply into the commit message: assembled from its parts in
+    /// structured mode, then cut to the requested shape.
+    fn render(&self, content: &str) -> String {
+        self.render_to(&mut io::stderr().lock(), content)
+    }
+
+    /// [`render`](Self::render), with any warning written to `err` instead
+    /// of stderr.
+    fn render_to(&self, err: &mut impl Write, content: &str) -> String {
+        let content = cut_at_stop(content, &self.stop);
+        if !self.structured {
+            return self.shape.apply(content);
+        }
+        match CommitParts::parse(content) {
+            Some(parts) => self.shape.apply(&parts.to_message()),
+            None => {
+                let _ = output::printer().warn_to(
+                    err,
+                    "Warning: the model did not reply with valid JSON; using its reply as plain text.",
+                );
+                self.shape.apply(content)
+            }
+        }
+    }
+
+    fn user_prompt(&self, diff: &str) -> String {
+        let prompt = match &self.prompt_template {
+            Some(template) => {
+                let files = DiffFile::split(diff);
+                let paths: Vec<&str> = files.iter().filter_map(DiffFile::path).collect();
+                let stat: String = files.iter().filter_map(DiffFile::stat).collect();
+                template::render(
+                    template,
+                    &[
+                        ("diff", diff),
+                        ("language", &self.language),
+                        ("branch", self.branch.as_deref().unwrap_or("unknown")),
+                        ("files", &paths.join("\n")),
+                        ("stat", stat.trim_end()),
+                    ],
+                )
+            }
+            None => match &self.diff_stat {
+                Some(stat) => format!(
+                    "Diff summary: {}\n\nHere is the git diff:\n```\n{}\n```",
+                    stat, diff
+                ),
+                None => format!("Here is the git diff:\n```\n{}\n```", diff),
             },
-        ],
+        };
+        match &self.previous_message {
+            Some(message) => format!(
+                "{}\n\nThe commit currently has this message:\n```\n{}\n```\n\
+                Improve it to match the diff rather than starting from scratch.",
+                prompt, message
+            ),
+            None => prompt,
+        }
+    }
+
+    /// Builds the provider-agnostic request for `diff`.
+    pub fn chat_request(&self, diff: &str) -> ChatRequest {
+        ChatRequest {
+            model: self.model.clone(),
+            system: self.system_prompt(),
+            user: self.user_prompt(diff),
+            stream: false,
+            n: 1,
+            temperature: widen(self.temperature),
+            top_p: widen(self.top_p),
+            frequency_penalty: widen(self.frequency_penalty),
+            presence_penalty: widen(self.presence_penalty),
+            seed: self.seed,
+            stop: self.stop.clone(),
+            structured: self.structured,
+            max_tokens: self.max_tokens,
+            reasoning: self
+                .reasoning_model
+                .unwrap_or_else(|| is_reasoning_model(&self.model)),
+            headers: self.headers.clone(),
+            http: self.http.clone(),
+        }
+    }
+}
+
+/// Widens a setting through its decimal form, so that 0.2 is sent as 0.2
+/// rather than 0.20000000298023224.
+fn widen(value: Option<f32>) -> Option<f64> {
+    value.and_then(|value| value.to_string().parse().ok())
+}
+
+/// OpenAI's o-series reasoning models (o1, o3-mini, ...) reject the `system`
+/// role and the sampling parameters chat models accept.
+fn is_reasoning_model(model: &str) -> bool {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    let mut chars = name.chars();
+    chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Shrinks `diff` to at most `max_bytes`. Every file header is kept, preceded
+/// by a summary of lines added and removed per file, and each hunk is cut down
+/// to its first few lines. Diffs that already fit are returned unchanged.
+pub fn truncate_diff(diff: &str, max_bytes: usize) -> String {
+    if diff.len() <= max_bytes {
+        return diff.to_string();
+    }
+
+    let files = DiffFile::split(diff);
+    let stat: String = files.iter().filter_map(DiffFile::stat).collect();
+    let render = |hunk_lines| {
+        let body: String = files.iter().map(|file| file.render(hunk_lines)).collect();
+        format!("{}\n{}", stat, body)
     };
+    for hunk_lines in (0..=MAX_HUNK_LINES).rev() {
+        let summary = render(hunk_lines);
+        if summary.len() <= max_bytes {
+            return summary;
+        }
+    }
+
+    // Even the headers alone are too large, so cut them off.
+    let summary = render(0);
+    let end = summary.floor_char_boundary(max_bytes.saturating_sub(TRUNCATION_MARKER.len() + 1));
+    format!("{}\n{}", &summary[..end], TRUNCATION_MARKER)
+}
+
+/// Shrinks `diff` to at most `max_bytes` by leaving out whole files, largest
+/// first. A summary of lines added and removed in every file comes first, so
+/// the model still sees what was left out.
+pub fn drop_largest_files(diff: &str, max_bytes: usize) -> String {
+    if diff.len() <= max_bytes {
+        return diff.to_string();
+    }
+
+    let files = DiffFile::split(diff);
+    let stat: String = files.iter().filter_map(DiffFile::stat).collect();
+    let rendered: Vec<String> = files.iter().map(|file| file.render(usize::MAX)).collect();
+    let mut largest_first: Vec<usize> = (0..rendered.len()).collect();
+    largest_first.sort_by_key(|&i| std::cmp::Reverse(rendered[i].len()));
+
+    let omitted_note = |count| format!("[…{} file(s) left out, see the summary above…]\n", count);
+    let mut kept = vec![true; rendered.len()];
+    let mut size = stat.len() + 1 + rendered.iter().map(String::len).sum::<usize>();
+    let mut omitted = 0;
+    for i in largest_first {
+        if size + omitted_note(omitted).len() <= max_bytes {
+            break;
+        }
+        kept[i] = false;
+        size -= rendered[i].len();
+        omitted += 1;
+    }
+
+    let mut shrunk = format!("{}\n", stat);
+    for (file, kept) in rendered.iter().zip(kept) {
+        if kept {
+            shrunk.push_str(file);
+        }
+    }
+    shrunk.push_str(&omitted_note(omitted));
+    // With every file gone only the summary is left, which may still be too
+    // large on its own.
+    truncate_diff(&shrunk, max_bytes)
+}
+
+/// One file's section of a unified diff.
+#[derive(Default)]
+pub(crate) struct DiffFile<'a> {
+    /// `diff --git`, `index`, `---`/`+++` and mode lines.
+    header: Vec<&'a str>,
+    /// Each hunk, starting with its `@@` line.
+    hunks: Vec<Vec<&'a str>>,
+}
+
+impl<'a> DiffFile<'a> {
+    /// Splits a diff at each `diff --git` line. Anything before the first one,
+    /// such as a `git show` commit header, becomes a section of its own.
+    pub(crate) fn split(diff: &'a str) -> Vec<DiffFile<'a>> {
+        let mut files = vec![DiffFile::default()];
+        for line in diff.lines() {
+            if line.starts_with("diff --git ") {
+                files.push(DiffFile::default());
+            }
+            let file = files.last_mut().unwrap();
+            if line.starts_with("@@") {
+                file.hunks.push(vec![line]);
+            } else if let Some(hunk) = file.hunks.last_mut() {
+                hunk.push(line);
+            } else {
+                file.header.push(line);
+            }
+        }
+        files.retain(|file| !file.header.is_empty() || !file.hunks.is_empty());
+        files
+    }
+
+    /// The file's path, or `None` for a section before the first file.
+    pub(crate) fn path(&self) -> Option<&'a str> {
+        self.header.iter().find_map(|line| {
+            line.strip_prefix("diff --git ")
+                .and_then(|paths| paths.rsplit_once(" b/"))
+                .map(|(_, path)| path)
+        })
+    }
+
+    /// A ` path | +added -removed` line, like `git diff --stat`.
+    fn stat(&self) -> Option<String> {
+        let path = self.path()?;
+        let lines = self.hunks.iter().flat_map(|hunk| &hunk[1..]);
+        let (added, removed) = lines.fold((0, 0), |(added, removed), line| {
+            match line.as_bytes().first() {
+                Some(b'+') => (added + 1, removed),
+                Some(b'-') => (added, removed + 1),
+                _ => (added, removed),
+            }
+        });
+        Some(format!(" {} | +{} -{}\n", path, added, removed))
+    }
+
+    /// The header and the first `hunk_lines` lines of each hunk.
+    pub(crate) fn render(&self, hunk_lines: usize) -> String {
+        let mut out = String::new();
+        for line in &self.header {
+            out.push_str(line);
+            out.push('\n');
+        }
+        for hunk in &self.hunks {
+            let (range, lines) = hunk.split_first().unwrap();
+            out.push_str(range);
+            out.push('\n');
+            for line in lines.iter().take(hunk_lines) {
+                out.push_str(line);
+                out.push('\n');
+            }
+            if lines.len() > hunk_lines {
+                out.push_str(TRUNCATION_MARKER);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+pub async fn generate_commit_message(
+    diff: &str,
+    provider: Provider,
+    opts: &GenerationOptions,
+) -> Result<Generation, AiCommitError> {
+    let started = Instant::now();
+    let (mut generation, truncated) =
+        shrinking(diff, async |diff| generate_from(diff, provider, opts).await).await?;
+    generation.truncated = truncated;
+    generation.latency = Some(started.elapsed());
+    Ok(generation)
+}
+
+async fn generate_from(
+    diff: &str,
+    provider: Provider,
+    opts: &GenerationOptions,
+) -> Result<Generation, AiCommitError> {
+    let request = opts.chat_request(diff);
+    let (response, model) = complete_with_retries(provider, &request, opts).await?;
+    if opts.accepts(&response.content) {
+        return Ok(Generation::new(opts, &response, model));
+    }
+
+    // Models occasionally ignore the format instructions; one more attempt
+    // usually fixes it.
+    progress::warn(FORMAT_RETRY_WARNING);
+    let (response, model) = complete_with_retries(provider, &request, opts).await?;
+    Ok(Generation::new(opts, &response, model))
+}
+
+/// Generates `count` candidate messages. Backends that can return several
+/// replies at once get a single request; others are called once per candidate.
+pub async fn generate_commit_messages(
+    diff: &str,
+    provider: Provider,
+    opts: &GenerationOptions,
+    count: u32,
+) -> Result<Vec<Generation>, AiCommitError> {
+    if !provider.build(opts).supports_multiple_choices() {
+        let mut candidates = Vec::new();
+        for _ in 0..count {
+            candidates.push(generate_commit_message(diff, provider, opts).await?);
+        }
+        return Ok(candidates);
+    }
 
-    let res = client
-        .post(url)
-        .bearer_auth(api_key)
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to send request: {}", e))?;
-
-    let status = res.status();
-    let body = res
-        .text()
-        .await
-        .map_err(|e| format!("Failed to read response body: {}", e))?;
-
-    if status.is_success() {
-        match serde_json::from_str::<OpenAiResponse>(&body) {
-            Ok(response_json) => {
-                if response_json.choices.is_empty() {
-                    Err("API response is empty.".to_string())
-                } else {
-                    Ok(response_json.choices[0].message.content.clone())
+    let started = Instant::now();
+    let ((replies, usage), truncated) = shrinking(diff, async |diff| {
+        let mut request = opts.chat_request(diff);
+        request.n = count;
+        let (response, model) = complete_with_retries(provider, &request, opts).await?;
+        log_fingerprint(opts, &response);
+        let mut usage = response.usage;
+        let mut replies: Vec<(Alternative, String)> = response
+            .into_alternatives()
+            .into_iter()
+            .map(|reply| (reply, model.clone()))
+            .collect();
+
+        // As for a single message, replies in the wrong format are asked for
+        // once more, all in one request.
+        let rejected = replies
+            .iter()
+            .filter(|(reply, _)| !opts.accepts(&reply.content))
+            .count();
+        if rejected > 0 {
+            progress::warn(FORMAT_RETRY_WARNING);
+            request.n = rejected as u32;
+            let (response, model) = complete_with_retries(provider, &request, opts).await?;
+            usage = TokenUsage::sum([usage, response.usage]);
+            let mut retried = response.into_alternatives().into_iter();
+            for (reply, reply_model) in replies
+                .iter_mut()
+                .filter(|(reply, _)| !opts.accepts(&reply.content))
+            {
+                if let Some(retry) = retried.next() {
+                    *reply = retry;
+                    *reply_model = model.clone();
                 }
             }
-            Err(e) => Err(format!(
-                "Failed to parse JSON response: {}. \nRaw response: {}",
-                e, body
-            )),
         }
-    } else {
-        Err(format!(
-            "API request failed with status {}. \nResponse: {}",
-            status, body
-        ))
+        Ok((replies, usage))
+    })
+    .await?;
+    let latency = Some(started.elapsed());
+    let candidates = replies
+        .into_iter()
+        .enumerate()
+        .map(|(i, (reply, model))| Generation {
+            message: opts.render(&reply.content),
+            model,
+            // The usage covers all candidates, so it is reported once, on
+            // the first.
+            usage: if i == 0 { usage } else { None },
+            truncated,
+            cut_off: reply.cut_off,
+            latency,
+        });
+    Ok(candidates.collect())
+}
+
+/// Generates a commit message, passing each chunk of text to `on_delta` as it
+/// arrives. Providers without streaming support deliver the whole message as
+/// a single chunk.
+pub async fn stream_commit_message(
+    diff: &str,
+    provider: Provider,
+    opts: &GenerationOptions,
+    mut on_delta: impl FnMut(&str) + Send,
+) -> Result<Generation, AiCommitError> {
+    let started = Instant::now();
+    let (mut generation, truncated) = shrinking(diff, async |diff| {
+        stream_from(diff, provider, opts, &mut on_delta).await
+    })
+    .await?;
+    generation.truncated = truncated;
+    generation.latency = Some(started.elapsed());
+    Ok(generation)
+}
+
+async fn stream_from(
+    diff: &str,
+    provider: Provider,
+    opts: &GenerationOptions,
+    mut on_delta: impl FnMut(&str) + Send,
+) -> Result<Generation, AiCommitError> {
+    if !provider.build(opts).supports_streaming() {
+        let (response, model) =
+            complete_with_retries(provider, &opts.chat_request(diff), opts).await?;
+        let generation = Generation::new(opts, &response, model);
+        on_delta(&generation.message);
+        return Ok(generation);
+    }
+
+    let mut request = opts.chat_request(diff);
+    request.stream = true;
+    if !opts.checks_reply() {
+        let mut shaped = ShapedStream::new(opts.shape, &opts.stop, &mut on_delta);
+        let (response, model) =
+            stream_with_retries(provider, &request, opts, &mut |delta| shaped.push(delta)).await?;
+        shaped.finish();
+        return Ok(Generation::new(opts, &response, model));
+    }
+
+    // A reply that fails the check is asked for again, so hold it back until
+    // one passes rather than print a message that is then replaced.
+    let (mut response, mut model) =
+        stream_with_retries(provider, &request, opts, &mut |_| {}).await?;
+    if !opts.accepts(&response.content) {
+        progress::warn(FORMAT_RETRY_WARNING);
+        (response, model) = stream_with_retries(provider, &request, opts, &mut |_| {}).await?;
+    }
+    let generation = Generation::new(opts, &response, model);
+    on_delta(&generation.message);
+    Ok(generation)
+}
+
+/// Runs `generate` on `diff`, and again on a diff with the largest files left
+/// out whenever the API rejects the request as too large. Also returns
+/// whether the diff had to be shrunk.
+async fn shrinking<T>(
+    diff: &str,
+    mut generate: impl AsyncFnMut(&str) -> Result<T, AiCommitError>,
+) -> Result<(T, bool), AiCommitError> {
+    let mut diff = diff.to_string();
+    let mut shrinks = 0;
+    loop {
+        match generate(&diff).await {
+            Ok(result) => return Ok((result, shrinks > 0)),
+            Err(err) if shrinks < MAX_SHRINKS && is_too_large(&err) => {
+                shrinks += 1;
+                let smaller = drop_largest_files(&diff, diff.len() / 2);
+                progress::note(&format!(
+                    "Diff is too large for the model ({}), retrying with {} of its {} bytes...",
+                    failure_reason(&err),
+                    smaller.len(),
+                    diff.len()
+                ));
+                diff = smaller;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Sends `request`, recovering from transient failures as described on
+/// [`Recovery`].
+async fn complete_with_retries(
+    provider: Provider,
+    request: &ChatRequest,
+    opts: &GenerationOptions,
+) -> Result<(ChatResponse, String), AiCommitError> {
+    let mut recovery = Recovery::new(provider, request, opts);
+    loop {
+        match recovery.backend.complete(&recovery.request).await {
+            Ok(response) => return Ok(recovery.finish(response)),
+            Err(err) => recovery.recover(err).await?,
+        }
+    }
+}
+
+async fn stream_with_retries(
+    provider: Provider,
+    request: &ChatRequest,
+    opts: &GenerationOptions,
+    on_delta: &mut (dyn for<'d> FnMut(&'d str) + Send),
+) -> Result<(ChatResponse, String), AiCommitError> {
+    let mut recovery = Recovery::new(provider, request, opts);
+    loop {
+        // Status errors surface before any text has been streamed, so a retry
+        // never repeats output.
+        match recovery
+            .backend
+            .complete_streaming(&recovery.request, on_delta)
+            .await
+        {
+            Ok(response) => return Ok(recovery.finish(response)),
+            Err(err) => recovery.recover(err).await?,
+        }
+    }
+}
+
+/// Retry state for one request. A rate-limited request first moves on to
+/// the next spare API key, then is retried with backoff, and finally moves
+/// on to each fallback model in turn.
+struct Recovery<'a> {
+    provider: Provider,
+    opts: &'a GenerationOptions,
+    backend: Box<dyn ApiProvider>,
+    request: ChatRequest,
+    spare_keys: std::slice::Iter<'a, String>,
+    keys_tried: usize,
+    fallbacks: std::slice::Iter<'a, String>,
+    attempt: u32,
+}
+
+impl<'a> Recovery<'a> {
+    fn new(provider: Provider, request: &ChatRequest, opts: &'a GenerationOptions) -> Recovery<'a> {
+        Recovery {
+            provider,
+            opts,
+            backend: provider.build(opts),
+            request: request.clone(),
+            spare_keys: opts.api_keys.iter(),
+            keys_tried: 1,
+            fallbacks: opts.fallback_models.iter(),
+            attempt: 0,
+        }
+    }
+
+    /// Prepares the next attempt after `err`, or returns the error once
+    /// nothing is left to try.
+    async fn recover(&mut self, err: AiCommitError) -> Result<(), AiCommitError> {
+        if !is_transient(&err) {
+            return Err(err);
+        }
+        let suggested_wait = match &err {
+            AiCommitError::ApiError { retry_after, .. } => *retry_after,
+            _ => None,
+        };
+        let wait_too_long = suggested_wait.is_some_and(|wait| wait > self.opts.max_retry_wait);
+        if is_rate_limited(&err)
+            && let Some(key) = self.spare_keys.next()
+        {
+            self.keys_tried += 1;
+            progress::note(&format!(
+                "API key is rate limited, trying key {} of {}...",
+                self.keys_tried,
+                self.opts.api_keys.len() + 1
+            ));
+            self.backend = self.provider.build(&GenerationOptions {
+                api_key: key.clone(),
+                ..self.opts.clone()
+            });
+        } else if self.attempt < self.opts.max_retries && !wait_too_long {
+            self.attempt += 1;
+            // The server knows best when its limit lifts.
+            let delay = suggested_wait.unwrap_or_else(|| {
+                backoff_delay(
+                    self.opts.retry_delay,
+                    self.attempt,
+                    self.opts.max_retry_wait,
+                )
+            });
+            progress::note(&format!(
+                "Request failed ({}), retrying in {:.1}s ({}/{})...",
+                failure_reason(&err),
+                delay.as_secs_f64(),
+                self.attempt,
+                self.opts.max_retries
+            ));
+            tokio::time::sleep(delay).await;
+        } else if self.backend.honours_model()
+            && let Some(model) = self.fallbacks.next()
+        {
+            progress::note(&format!(
+                "Model {} failed ({}), falling back to {}...",
+                self.request.model, err, model
+            ));
+            self.request.model = model.to_string();
+            self.attempt = 0;
+        } else {
+            return Err(self.give_up(err, wait_too_long));
+        }
+        Ok(())
+    }
+
+    /// Pairs the reply with the model that produced it, noting on stderr
+    /// when that was a fallback model.
+    fn finish(&self, response: ChatResponse) -> (ChatResponse, String) {
+        if self.request.model != self.opts.model {
+            progress::note(&format!(
+                "Message generated by fallback model {}.",
+                self.request.model
+            ));
+        }
+        (response, self.request.model.clone())
+    }
+
+    /// Explains why a rate-limited request was not retried further: how
+    /// many keys were tried, and whether the suggested wait was over the limit.
+    fn give_up(&self, err: AiCommitError, wait_too_long: bool) -> AiCommitError {
+        match err {
+            AiCommitError::ApiError {
+                status: 429,
+                mut body,
+                retry_after,
+            } => {
+                if self.keys_tried > 1 {
+                    body = format!("{} (tried {} API keys)", body, self.keys_tried);
+                }
+                if wait_too_long {
+                    body = format!(
+                        "{}. Not retrying, as that is longer than max-retry-wait-secs ({}).",
+                        body,
+                        format_wait(self.opts.max_retry_wait)
+                    );
+                }
+                AiCommitError::ApiError {
+                    status: 429,
+                    body,
+                    retry_after,
+                }
+            }
+            err => err,
+        }
+    }
+}
+
+fn is_rate_limited(err: &AiCommitError) -> bool {
+    matches!(err, AiCommitError::ApiError { status: 429, .. })
+}
+
+/// A request rejected because the prompt is too large: 413, or a 400 that
+/// names the model's context length.
+fn is_too_large(err: &AiCommitError) -> bool {
+    match err {
+        AiCommitError::ApiError { status: 413, .. } => true,
+        AiCommitError::ApiError {
+            status: 400, body, ..
+        } => {
+            let body = body.to_lowercase();
+            [
+                "context length",
+                "context_length",
+                "context window",
+                "prompt is too long",
+            ]
+            .iter()
+            .any(|phrase| body.contains(phrase))
+        }
+        _ => false,
+    }
+}
+
+/// Rate limiting, server-side failures and connection problems that are
+/// worth retrying. Other 4xx errors fail straight away.
+fn is_transient(err: &AiCommitError) -> bool {
+    match err {
+        AiCommitError::ApiError { status, .. } => matches!(status, 429 | 500 | 502 | 503 | 504),
+        AiCommitError::Network(e) => e.is_connect(),
+        // A slow response would most likely be slow again.
+        AiCommitError::Timeout { connect, .. } => *connect,
+        _ => false,
+    }
+}
+
+/// An exponentially growing delay with ±20% jitter, so that many clients
+/// hitting the same limit don't retry in lockstep. Never longer than `max`.
+fn backoff_delay(base: Duration, attempt: u32, max: Duration) -> Duration {
+    let factor = 2u32
+        .checked_pow(attempt.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    let backoff = base.saturating_mul(factor).min(max);
+    Duration::try_from_secs_f64(backoff.as_secs_f64() * (fastrand::f64() * 0.4 + 0.8))
+        .unwrap_or(max)
+        .min(max)
+}
+
+fn failure_reason(err: &AiCommitError) -> String {
+    match err {
+        AiCommitError::ApiError { status, .. } => format!("status {}", status),
+        AiCommitError::Timeout { .. } => "connect timeout".to_string(),
+        _ => "connection error".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn backoff_delay_doubles_within_jitter() {
+        let base = Duration::from_millis(100);
+        for attempt in 1..=4 {
+            let expected = 100.0 * 2f64.powi(attempt as i32 - 1);
+            let delay = backoff_delay(base, attempt, MAX_RETRY_WAIT).as_secs_f64() * 1000.0;
+            assert!(delay >= expected * 0.8 - 1.0 && delay <= expected * 1.2 + 1.0);
+        }
+    }
+
+    #[test]
+    fn backoff_delay_is_capped_at_max_wait() {
+        let max = Duration::from_secs(60);
+        assert!(backoff_delay(Duration::from_secs(1), 10, max) <= max);
+        assert!(backoff_delay(Duration::from_secs(1), 40, max) <= max);
+        assert!(backoff_delay(Duration::from_secs(u64::MAX / 2), 64, max) <= max);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried_until_they_succeed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).set_body_string("slow down"))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": "fix: handle rate limits" },
+                    "finish_reason": "stop"
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let opts = GenerationOptions {
+            api_key: "test".to_string(),
+            url: format!("{}/v1/chat/completions", server.uri()),
+            model: "gpt-4o-mini".to_string(),
+            max_retries: 3,
+            retry_delay: Duration::from_millis(1),
+            max_retry_wait: MAX_RETRY_WAIT,
+            ..GenerationOptions::default()
+        };
+        let generation = generate_commit_message("diff --git a/x b/x\n", Provider::OpenAi, &opts)
+            .await
+            .unwrap();
+
+        assert_eq!(generation.message, "fix: handle rate limits");
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn candidates_in_the_wrong_format_are_asked_for_once_more() {
+        let server = MockServer::start().await;
+        let reply = |choices: serde_json::Value| {
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "choices": choices }))
+        };
+        Mock::given(method("POST"))
+            .respond_with(reply(serde_json::json!([
+                { "message": { "content": "fix: update the lexer" }, "finish_reason": "stop" },
+                { "message": { "content": "Updated the parser" }, "finish_reason": "stop" }
+            ])))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(reply(serde_json::json!([
+                { "message": { "content": "fix: update the parser" }, "finish_reason": "length" }
+            ])))
+            .mount(&server)
+            .await;
+
+        let opts = GenerationOptions {
+            url: format!("{}/v1/chat/completions", server.uri()),
+            format: CommitFormat::Conventional,
+            ..GenerationOptions::default()
+        };
+        let candidates =
+            generate_commit_messages("diff --git a/x b/x\n", Provider::OpenAi, &opts, 2)
+                .await
+                .unwrap();
+
+        let messages: Vec<&str> = candidates.iter().map(|c| c.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["fix: update the lexer", "fix: update the parser"]
+        );
+        assert!(!candidates[0].cut_off);
+        assert!(candidates[1].cut_off);
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].body_json::<serde_json::Value>().unwrap()["n"],
+            2
+        );
+        // One reply is the default, so `n` is left out.
+        let retry: serde_json::Value = requests[1].body_json().unwrap();
+        assert!(retry.get("n").is_none());
+    }
+
+    #[tokio::test]
+    async fn fallback_model_is_sent_as_the_azure_deployment() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/primary/chat/completions"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("down"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/backup/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": "fix: x" } }]
+            })))
+            .mount(&server)
+            .await;
+
+        let opts = GenerationOptions {
+            url: server.uri(),
+            model: "primary".to_string(),
+            fallback_models: vec!["backup".to_string()],
+            max_retries: 0,
+            ..GenerationOptions::default()
+        };
+        let generation = generate_commit_message("diff --git a/x b/x\n", Provider::Azure, &opts)
+            .await
+            .unwrap();
+
+        assert_eq!(generation.model, "backup");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fallback_is_not_tried_when_the_url_names_the_model() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("down"))
+            .mount(&server)
+            .await;
+
+        let opts = GenerationOptions {
+            url: format!(
+                "{}/openai/deployments/primary/chat/completions?api-version=2024-02-01",
+                server.uri()
+            ),
+            model: "primary".to_string(),
+            fallback_models: vec!["backup".to_string()],
+            max_retries: 0,
+            ..GenerationOptions::default()
+        };
+        let result = generate_commit_message("diff --git a/x b/x\n", Provider::Azure, &opts).await;
+
+        assert!(matches!(
+            result,
+            Err(AiCommitError::ApiError { status: 500, .. })
+        ));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejected_streamed_reply_is_not_printed_before_the_retry() {
+        let server = MockServer::start().await;
+        let sse = |content: &str| {
+            let chunk = serde_json::json!({ "choices": [{ "delta": { "content": content } }] });
+            format!("data: {}\n\ndata: [DONE]\n\n", chunk)
+        };
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(sse("Updated the parser"), "text/event-stream"),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(sse("fix: update the parser"), "text/event-stream"),
+            )
+            .mount(&server)
+            .await;
+
+        let opts = GenerationOptions {
+            url: format!("{}/v1/chat/completions", server.uri()),
+            format: CommitFormat::Conventional,
+            stream: true,
+            ..GenerationOptions::default()
+        };
+        let mut printed = String::new();
+        let generation =
+            stream_commit_message("diff --git a/x b/x\n", Provider::OpenAi, &opts, |delta| {
+                printed.push_str(delta)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(generation.message, "fix: update the parser");
+        assert_eq!(printed, "fix: update the parser");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reply_in_the_wrong_format_is_asked_for_once_more() {
+        let server = MockServer::start().await;
+        let reply = |content: &str| {
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": content } }]
+            }))
+        };
+        Mock::given(method("POST"))
+            .respond_with(reply("Updated the parser"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(reply("fix: update the parser"))
+            .mount(&server)
+            .await;
+
+        let opts = GenerationOptions {
+            url: format!("{}/v1/chat/completions", server.uri()),
+            format: CommitFormat::Conventional,
+            ..GenerationOptions::default()
+        };
+        let generation = generate_commit_message("diff --git a/x b/x\n", Provider::OpenAi, &opts)
+            .await
+            .unwrap();
+
+        assert_eq!(generation.message, "fix: update the parser");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    /// A diff of `files` files, each with one hunk of `lines` added lines.
+    fn diff(files: &[(&str, usize)]) -> String {
+        let mut diff = String::new();
+        for (name, lines) in files {
+            diff.push_str(&format!(
+                "diff --git a/{name} b/{name}\nindex 1111111..2222222 100644\n--- a/{name}\n+++ b/{name}\n@@ -0,0 +1,{lines} @@\n"
+            ));
+            for i in 0..*lines {
+                diff.push_str(&format!("+line {} of {}\n", i, name));
+            }
+        }
+        diff
+    }
+
+    #[test]
+    fn truncate_diff_leaves_small_diffs_alone() {
+        let small = diff(&[("a.rs", 3)]);
+        assert_eq!(truncate_diff(&small, small.len()), small);
+    }
+
+    #[test]
+    fn truncate_diff_cuts_hunks_and_keeps_every_header() {
+        let full = diff(&[("a.rs", 100), ("b.rs", 100), ("c.rs", 5)]);
+        let shrunk = truncate_diff(&full, full.len() / 3);
+
+        assert!(shrunk.len() <= full.len() / 3);
+        assert!(shrunk.starts_with(" a.rs | +100 -0\n b.rs | +100 -0\n c.rs | +5 -0\n"));
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            assert!(shrunk.contains(&format!("diff --git a/{name} b/{name}\n")));
+            assert!(shrunk.contains(&format!("+++ b/{name}\n")));
+        }
+        assert!(shrunk.contains("+line 0 of a.rs\n"));
+        assert!(!shrunk.contains("+line 99 of a.rs\n"));
+        assert_eq!(shrunk.matches(TRUNCATION_MARKER).count(), 2);
+        // The short hunk fits whole and gets no marker.
+        assert!(shrunk.contains("+line 4 of c.rs\n"));
+    }
+
+    #[test]
+    fn truncate_diff_cuts_the_headers_themselves_as_a_last_resort() {
+        let full = diff(&[("a.rs", 50), ("b.rs", 50)]);
+        let shrunk = truncate_diff(&full, 60);
+
+        assert!(shrunk.len() <= 60);
+        assert!(shrunk.ends_with(TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn subject_only_keeps_the_first_line_within_the_limit() {
+        let long = format!("\n  feat: {}\n\n- a bullet\n", "x".repeat(100));
+        let subject = MessageShape::SubjectOnly.apply(&long);
+
+        assert_eq!(subject.chars().count(), MAX_SUBJECT_CHARS);
+        assert!(subject.starts_with("feat: xxx"));
+        assert_eq!(
+            MessageShape::SubjectOnly.apply("fix: typo\n\nBody."),
+            "fix: typo"
+        );
+        // Multi-byte characters count once each.
+        let wide = "é".repeat(80);
+        assert_eq!(MessageShape::SubjectOnly.apply(&wide), "é".repeat(72));
+    }
+
+    #[test]
+    fn subject_only_changes_the_system_prompt() {
+        let opts = GenerationOptions {
+            shape: MessageShape::SubjectOnly,
+            ..GenerationOptions::default()
+        };
+        assert!(
+            opts.system_prompt()
+                .contains("Output only a single subject line of at most 72 characters.")
+        );
+        assert!(
+            !GenerationOptions::default()
+                .system_prompt()
+                .contains("single subject line")
+        );
+    }
+
+    #[test]
+    fn drop_largest_files_leaves_out_the_biggest_first() {
+        let full = diff(&[("small.rs", 3), ("huge.rs", 200), ("medium.rs", 20)]);
+        let shrunk = drop_largest_files(&full, full.len() / 2);
+
+        assert!(shrunk.len() <= full.len() / 2);
+        assert!(shrunk.starts_with(" small.rs | +3 -0\n huge.rs | +200 -0\n medium.rs | +20 -0\n"));
+        assert!(!shrunk.contains("diff --git a/huge.rs"));
+        assert!(shrunk.contains("+line 2 of small.rs\n"));
+        assert!(shrunk.contains("+line 19 of medium.rs\n"));
+        assert!(shrunk.contains("[…1 file(s) left out, see the summary above…]"));
+    }
+
+    #[test]
+    fn drop_largest_files_falls_back_to_the_truncated_summary() {
+        let full = diff(&[("a.rs", 50), ("b.rs", 50)]);
+        let shrunk = drop_largest_files(&full, 40);
+
+        assert!(shrunk.len() <= 40);
+        assert!(!shrunk.contains("diff --git"));
+        assert!(shrunk.ends_with(TRUNCATION_MARKER));
+    }
+
+    fn stops(stops: &[&str]) -> Vec<String> {
+        stops.iter().map(|stop| stop.to_string()).collect()
+    }
+
+    #[test]
+    fn cut_at_stop_cuts_at_the_earliest_stop() {
+        let content = "feat: add x\n\nExplanation: because\n---\nmore";
+        assert_eq!(
+            cut_at_stop(content, &stops(&["---", "Explanation:"])),
+            "feat: add x"
+        );
+        assert_eq!(cut_at_stop(content, &stops(&["nowhere"])), content);
+        assert_eq!(cut_at_stop(content, &stops(&[""])), content);
+        assert_eq!(cut_at_stop(content, &[]), content);
+    }
+
+    #[test]
+    fn cut_at_stop_respects_multi_byte_characters() {
+        assert_eq!(cut_at_stop("fix: café ✓ done", &stops(&["✓"])), "fix: café");
+        assert_eq!(
+            cut_at_stop("修复：缓存。説明", &stops(&["説明"])),
+            "修复：缓存。"
+        );
+        assert_eq!(cut_at_stop("é✓", &stops(&["é"])), "");
+        assert_eq!(cut_at_stop("ok 🎉", &stops(&["🎉"])), "ok");
+    }
+
+    #[test]
+    fn streamed_output_stops_before_a_stop_string_split_across_deltas() {
+        let stop = stops(&["Explanation:"]);
+        let mut printed = String::new();
+        let mut on_delta = |delta: &str| printed.push_str(delta);
+        let mut shaped = ShapedStream::new(MessageShape::Any, &stop, &mut on_delta);
+        for delta in ["feat: add x\n\nExpl", "anation: because", " reasons"] {
+            shaped.push(delta);
+        }
+        shaped.finish();
+        assert_eq!(printed, "feat: add x\n\n");
+
+        let mut printed = String::new();
+        let mut on_delta = |delta: &str| printed.push_str(delta);
+        let mut shaped = ShapedStream::new(MessageShape::Any, &stop, &mut on_delta);
+        shaped.push("fix: typo in Ex");
+        shaped.finish();
+        assert_eq!(printed, "fix: typo in Ex");
+    }
+
+    #[test]
+    fn streamed_subject_holds_back_trailing_whitespace_until_the_newline() {
+        let mut printed = String::new();
+        let mut on_delta = |delta: &str| printed.push_str(delta);
+        let mut shaped = ShapedStream::new(MessageShape::WithBody, &[], &mut on_delta);
+        for delta in ["feat: x  ", "\nbody"] {
+            shaped.push(delta);
+        }
+        shaped.finish();
+
+        assert_eq!(printed, "feat: x\n\nbody");
+        assert_eq!(printed, MessageShape::WithBody.apply("feat: x  \nbody"));
+    }
+
+    #[test]
+    fn prompt_template_gets_files_stat_and_branch() {
+        let opts = GenerationOptions {
+            language: "en".to_string(),
+            prompt_template: Some(
+                "{language} on {branch}\n{files}\n{stat}\n---\n{diff}".to_string(),
+            ),
+            ..GenerationOptions::default()
+        };
+        let diff = diff(&[("a.rs", 2), ("b.rs", 1)]);
+
+        assert_eq!(
+            opts.user_prompt(&diff),
+            format!(
+                "en on unknown\na.rs\nb.rs\n a.rs | +2 -0\n b.rs | +1 -0\n---\n{}",
+                diff
+            )
+        );
+    }
+
+    #[test]
+    fn structured_reply_that_is_not_json_is_used_as_plain_text_with_a_warning() {
+        let opts = GenerationOptions {
+            structured: true,
+            ..GenerationOptions::default()
+        };
+        let mut err = Vec::new();
+        let message = opts.render_to(&mut err, "fix: handle empty diffs");
+
+        assert_eq!(message, "fix: handle empty diffs");
+        assert!(
+            String::from_utf8(err)
+                .unwrap()
+                .contains("did not reply with valid JSON")
+        );
+
+        let mut err = Vec::new();
+        let json =
+            r#"{"type": "fix", "scope": null, "subject": "x", "body": null, "breaking": false}"#;
+        assert_eq!(opts.render_to(&mut err, json), "fix: x");
+        assert!(err.is_empty());
     }
 }
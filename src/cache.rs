@@ -0,0 +1,171 @@
+// ===================================================================
+// Response Cache
+// ===================================================================
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{fs, io};
+
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::AiCommitError;
+use crate::providers::{ChatRequest, Provider};
+
+/// How long a cached message is reused, unless configured otherwise.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub message: String,
+    pub model: String,
+    /// Unix time the message was generated.
+    pub timestamp: i64,
+}
+
+impl Entry {
+    /// Seconds since the entry was stored.
+    pub fn age(&self) -> i64 {
+        chrono::Utc::now().timestamp() - self.timestamp
+    }
+}
+
+fn cache_path() -> Result<PathBuf, AiCommitError> {
+    let dirs = ProjectDirs::from("com", "github", "ai-commit").ok_or_else(|| {
+        AiCommitError::Cache(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Failed to get the cache directory",
+        ))
+    })?;
+    Ok(dirs.cache_dir().join("responses.json"))
+}
+
+/// Hex SHA-256 of everything that shapes the reply: the endpoint, the model,
+/// the prompts (which carry the language, format, custom prompt and diff)
+/// and the sampling settings sent with them.
+pub fn key(request: &ChatRequest, provider: Provider, url: &str) -> String {
+    let settings = format!(
+        "{:?}",
+        (
+            request.n,
+            request.temperature,
+            request.top_p,
+            request.frequency_penalty,
+            request.presence_penalty,
+            request.seed,
+            &request.stop,
+            request.structured,
+            request.max_tokens,
+            request.reasoning,
+            &request.headers,
+        )
+    );
+    let mut hasher = Sha256::new();
+    for part in [
+        provider.name(),
+        url,
+        &request.model,
+        &request.system,
+        &request.user,
+        &settings,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn load() -> BTreeMap<String, Entry> {
+    cache_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// The message cached under `key`, if it is younger than `ttl`. A missing
+/// or unreadable cache is treated as empty.
+pub fn get(key: &str, ttl: Duration) -> Option<Entry> {
+    load()
+        .remove(key)
+        .filter(|entry| entry.age() < ttl.as_secs() as i64)
+}
+
+/// Caches `message` under `key`, dropping entries older than `ttl`.
+pub fn store(key: &str, message: &str, model: &str, ttl: Duration) -> Result<(), AiCommitError> {
+    let mut entries = load();
+    entries.retain(|_, entry| entry.age() < ttl.as_secs() as i64);
+    entries.insert(
+        key.to_string(),
+        Entry {
+            message: message.to_string(),
+            model: model.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        },
+    );
+    let path = cache_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(AiCommitError::Cache)?;
+    }
+    let json = serde_json::to_string(&entries).expect("cache entries serialize");
+    fs::write(path, json).map_err(AiCommitError::Cache)
+}
+
+/// Deletes every cached message.
+pub fn clear() -> Result<(), AiCommitError> {
+    match fs::remove_file(cache_path()?) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(AiCommitError::Cache(e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_commit::GenerationOptions;
+
+    #[test]
+    fn key_covers_the_endpoint_and_sampling_settings() {
+        let opts = GenerationOptions::default();
+        let request = |opts: &GenerationOptions| opts.chat_request("diff --git a/x b/x\n");
+        let base = key(&request(&opts), Provider::OpenAi, "https://a.example");
+
+        assert_eq!(
+            base,
+            key(&request(&opts), Provider::OpenAi, "https://a.example")
+        );
+        assert_ne!(
+            base,
+            key(&request(&opts), Provider::OpenAi, "https://b.example")
+        );
+        assert_ne!(
+            base,
+            key(&request(&opts), Provider::Ollama, "https://a.example")
+        );
+        for changed in [
+            GenerationOptions {
+                temperature: Some(0.1),
+                ..opts.clone()
+            },
+            GenerationOptions {
+                seed: Some(7),
+                ..opts.clone()
+            },
+            GenerationOptions {
+                max_tokens: Some(100),
+                ..opts.clone()
+            },
+            GenerationOptions {
+                stop: vec!["---".to_string()],
+                ..opts.clone()
+            },
+        ] {
+            assert_ne!(
+                base,
+                key(&request(&changed), Provider::OpenAi, "https://a.example")
+            );
+        }
+    }
+}
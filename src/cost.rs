@@ -0,0 +1,67 @@
+// ===================================================================
+// Cost Estimates
+// ===================================================================
+
+use crate::config::Config;
+
+/// USD per million prompt and completion tokens, matched by model-name
+/// prefix. More specific names come first.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("o3-mini", 1.10, 4.40),
+    ("o4-mini", 1.10, 4.40),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("deepseek-chat", 0.27, 1.10),
+];
+
+/// What a model charges, in USD per million tokens.
+#[derive(Debug, Clone, Copy)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Price {
+    /// Cost in USD of `prompt` input and `completion` output tokens.
+    pub fn cost(&self, prompt: u64, completion: u64) -> f64 {
+        (prompt as f64 * self.input + completion as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// The price of `model`, from `[models]` in the config where set and the
+/// built-in table otherwise. `None` if neither knows it.
+pub fn price(model: &str, config: &Config) -> Option<Price> {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    let builtin = PRICES
+        .iter()
+        .find(|(prefix, _, _)| name.starts_with(prefix))
+        .map(|(_, input, output)| Price {
+            input: *input,
+            output: *output,
+        });
+    let settings = config.model_settings(model);
+    Some(Price {
+        input: settings
+            .and_then(|settings| settings.input_price)
+            .or(builtin.map(|price| price.input))?,
+        output: settings
+            .and_then(|settings| settings.output_price)
+            .or(builtin.map(|price| price.output))?,
+    })
+}
+
+/// `$0.0012`; fractions of a cent matter at these sizes.
+pub fn format(cost: f64) -> String {
+    format!("${:.4}", cost)
+}
@@ -0,0 +1,153 @@
+// ===================================================================
+// Setup Diagnostics
+// ===================================================================
+
+use std::{env, path::Path, process::Command, time::Duration};
+
+use reqwest::Url;
+
+use crate::ai_commit::GenerationOptions;
+use crate::cli::GenerateArgs;
+use crate::config::Config;
+use crate::{resolve_editor, resolve_options};
+
+/// Runs every check, printing ✓ or ✗ with a suggested fix for each failure.
+/// Returns the number of failed checks.
+pub async fn run_doctor(config: &Config) -> i32 {
+    let mut failures = 0;
+    let mut report = |result: Result<String, (String, String)>| match result {
+        Ok(message) => println!("✓ {}", message),
+        Err((problem, fix)) => {
+            failures += 1;
+            println!("✗ {}\n  fix: {}", problem, fix);
+        }
+    };
+
+    report(check_git());
+    report(check_staged_changes());
+    match check_api_key(config) {
+        Ok(opts) => {
+            report(Ok(format!("API key configured for {}", opts.url)));
+            report(check_url(&opts).await);
+        }
+        Err(problem) => report(Err(problem)),
+    }
+    report(check_temp_dir());
+    report(check_editor(config));
+
+    failures
+}
+
+fn check_git() -> Result<String, (String, String)> {
+    match Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        _ => Err((
+            "git was not found".to_string(),
+            "install git and make sure it is on your PATH".to_string(),
+        )),
+    }
+}
+
+fn check_staged_changes() -> Result<String, (String, String)> {
+    // `--quiet` exits with 1 when there are differences.
+    let status = Command::new("git")
+        .args(["diff", "--staged", "--quiet"])
+        .status();
+    match status.ok().and_then(|status| status.code()) {
+        Some(1) => Ok("there are staged changes".to_string()),
+        Some(0) => Err((
+            "nothing is staged".to_string(),
+            "stage changes with `git add <files>`".to_string(),
+        )),
+        _ => Err((
+            "not inside a git repository".to_string(),
+            "run ai_commit from within a repository".to_string(),
+        )),
+    }
+}
+
+/// Returns the resolved settings when a usable key (or no key) is needed.
+fn check_api_key(config: &Config) -> Result<GenerationOptions, (String, String)> {
+    let args = GenerateArgs::default();
+    match resolve_options(&args, config) {
+        Ok((_, opts)) => Ok(opts),
+        Err(e) => Err((
+            e.to_string(),
+            "run `ai_commit config validate` for details".to_string(),
+        )),
+    }
+}
+
+/// Sends a HEAD request through the configured proxy, if any.
+async fn check_url(opts: &GenerationOptions) -> Result<String, (String, String)> {
+    let url = &opts.url;
+    if url.starts_with("mock://") {
+        return Ok("mock endpoint needs no network".to_string());
+    }
+    let http = opts.http.with_timeout(Duration::from_secs(5));
+    let client = match Url::parse(url)
+        .map_err(|e| e.to_string())
+        .and_then(|parsed| http.client(&parsed).map_err(|e| e.to_string()))
+    {
+        Ok(client) => client,
+        Err(e) => {
+            return Err((
+                format!("cannot reach {}: {}", url, e),
+                "check the url and proxy settings with `ai_commit config show`".to_string(),
+            ));
+        }
+    };
+    // Any HTTP response, even 404 or 405, means the server is reachable.
+    match client.head(url).send().await {
+        Ok(res) => Ok(format!(
+            "{} is reachable (HTTP {})",
+            url,
+            res.status().as_u16()
+        )),
+        Err(e) => Err((
+            format!("{} is not reachable: {}", url, e),
+            "check the URL with `ai_commit config get url`, your network and any proxy settings"
+                .to_string(),
+        )),
+    }
+}
+
+fn check_temp_dir() -> Result<String, (String, String)> {
+    match tempfile::tempfile() {
+        Ok(_) => Ok(format!("{} is writable", env::temp_dir().display())),
+        Err(e) => Err((
+            format!("cannot write to {}: {}", env::temp_dir().display(), e),
+            "set TMPDIR to a writable directory".to_string(),
+        )),
+    }
+}
+
+fn check_editor(config: &Config) -> Result<String, (String, String)> {
+    let editor = resolve_editor(config.editor.as_deref());
+    let program = editor.split_whitespace().next().unwrap_or_default();
+    if find_program(program) {
+        Ok(format!("editor `{}` found", editor))
+    } else {
+        Err((
+            format!("editor `{}` was not found", program),
+            "set $EDITOR or run `ai_commit config set-editor <EDITOR>`".to_string(),
+        ))
+    }
+}
+
+/// Whether `program` is a path to a file or can be found on `PATH`.
+fn find_program(program: &str) -> bool {
+    if program.is_empty() {
+        return false;
+    }
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        return Path::new(program).is_file();
+    }
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            dir.join(program).is_file() || dir.join(format!("{}.exe", program)).is_file()
+        })
+    })
+}
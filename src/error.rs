@@ -0,0 +1,118 @@
+// ===================================================================
+// Error Types
+// ===================================================================
+
+use std::io;
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AiCommitError {
+    #[error("Failed to send request: {0}")]
+    Network(#[from] reqwest::Error),
+
+    #[error("Failed to parse JSON response: {source}. \nRaw response: {body}")]
+    Serialization {
+        source: serde_json::Error,
+        body: String,
+    },
+
+    #[error("Failed to read streamed response: {0}")]
+    Stream(String),
+
+    /// The server did not answer in time. Connect timeouts are kept apart
+    /// from slow responses, since they point at different problems.
+    #[error("{message}")]
+    Timeout { message: String, connect: bool },
+
+    #[error("API response is empty.")]
+    EmptyResponse,
+
+    #[error("API request failed with status {status}: {body}")]
+    ApiError {
+        status: u16,
+        body: String,
+        /// How long a rate limit says to wait before trying again.
+        retry_after: Option<Duration>,
+    },
+
+    #[error("Failed to read config file: {0}")]
+    ConfigRead(io::Error),
+
+    #[error("Failed to parse config file: {0}")]
+    ConfigParse(#[from] toml::de::Error),
+
+    #[error("Failed to write config file: {0}")]
+    ConfigWrite(io::Error),
+
+    #[error("Failed to serialize config: {0}")]
+    ConfigSerialize(#[from] toml::ser::Error),
+
+    /// Missing or expired cloud credentials (e.g. AWS for Bedrock).
+    #[error("{0}")]
+    Credentials(String),
+
+    /// The diff could not be read from stdin or a file.
+    #[error("Failed to read diff from {from}: {error}")]
+    DiffRead {
+        from: String,
+        #[source]
+        error: io::Error,
+    },
+
+    /// `config get` was asked for an unknown or unset key.
+    #[error("{0}")]
+    ConfigLookup(String),
+
+    /// Missing or invalid settings, e.g. an unset API key.
+    #[error("{0}")]
+    Config(String),
+
+    /// The usage statistics database could not be read or written.
+    #[error("Failed to access usage statistics: {0}")]
+    Stats(#[from] rusqlite::Error),
+
+    /// The response cache could not be written or cleared.
+    #[error("Failed to access the response cache: {0}")]
+    Cache(io::Error),
+
+    /// A git command could not be run or exited with an error.
+    #[error("git {command} failed: {message}")]
+    Git { command: String, message: String },
+
+    /// The commit message editor could not be run, or its file could not be
+    /// written or read back.
+    #[error("Editor failed: {0}")]
+    Editor(String),
+
+    /// Ctrl-C was pressed while the editor was open.
+    #[error("aborted")]
+    Interrupted,
+}
+
+impl AiCommitError {
+    /// Process exit code for this failure, so scripts can tell problems apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AiCommitError::Network(_)
+            | AiCommitError::Stream(_)
+            | AiCommitError::Timeout { .. }
+            | AiCommitError::ConfigLookup(_) => 1,
+            AiCommitError::ConfigRead(_)
+            | AiCommitError::ConfigParse(_)
+            | AiCommitError::ConfigWrite(_)
+            | AiCommitError::ConfigSerialize(_)
+            | AiCommitError::Credentials(_)
+            | AiCommitError::DiffRead { .. }
+            | AiCommitError::Config(_)
+            | AiCommitError::Stats(_)
+            | AiCommitError::Cache(_) => 2,
+            AiCommitError::ApiError { .. } => 3,
+            AiCommitError::Serialization { .. } | AiCommitError::EmptyResponse => 4,
+            AiCommitError::Git { .. } | AiCommitError::Editor(_) => 5,
+            // The shell convention for SIGINT.
+            AiCommitError::Interrupted => 130,
+        }
+    }
+}
@@ -0,0 +1,432 @@
+// ===================================================================
+// Git Diff Sources and Repository Context
+// ===================================================================
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use tracing::debug;
+
+use crate::error::AiCommitError;
+
+/// Where the diff to describe comes from.
+#[derive(Debug, Clone)]
+pub enum DiffSource {
+    /// `git diff --staged` in the current repository, limited to these
+    /// pathspecs if there are any.
+    Staged(Vec<String>),
+    /// A diff that was piped in, e.g. `git show SHA | ai_commit --stdin`.
+    Stdin(String),
+    /// A patch file on disk.
+    File(PathBuf),
+    /// The last commit plus anything staged on top, i.e. what
+    /// `git commit --amend` would record.
+    LastCommit,
+    /// Changes to tracked files that aren't committed, staged or not
+    /// (`git diff HEAD`), which is what `git stash push` saves.
+    Unstaged,
+}
+
+impl DiffSource {
+    /// Reads all of stdin into a `Stdin` source.
+    pub fn from_stdin() -> Result<DiffSource, AiCommitError> {
+        let mut diff = String::new();
+        io::stdin()
+            .read_to_string(&mut diff)
+            .map_err(|error| AiCommitError::DiffRead {
+                from: "stdin".to_string(),
+                error,
+            })?;
+        Ok(DiffSource::Stdin(diff))
+    }
+
+    /// Returns the diff text. An empty string means there is nothing to
+    /// describe.
+    pub fn read(&self) -> Result<String, AiCommitError> {
+        match self {
+            // Outside a repository `git diff` prints its whole usage.
+            DiffSource::Staged(_) | DiffSource::LastCommit | DiffSource::Unstaged
+                if repo_root().is_none() =>
+            {
+                Err(AiCommitError::Git {
+                    command: "diff".to_string(),
+                    message:
+                        "not inside a git repository; use --stdin or --diff-file for other diffs"
+                            .to_string(),
+                })
+            }
+            DiffSource::Staged(paths) => staged_diff(paths),
+            DiffSource::LastCommit => last_commit_diff(),
+            DiffSource::Unstaged => {
+                let excludes = ignored_pathspecs();
+                run_git(&diff_args(&["HEAD"], &[], &excludes))
+            }
+            DiffSource::Stdin(diff) => Ok(diff.clone()),
+            DiffSource::File(path) => {
+                fs::read_to_string(path).map_err(|error| AiCommitError::DiffRead {
+                    from: path.display().to_string(),
+                    error,
+                })
+            }
+        }
+    }
+
+    /// A one-line summary of `diff`, the text read from this source, e.g.
+    /// `3 files changed, 42 insertions(+), 7 deletions(-)`.
+    pub fn stat(&self, diff: &str) -> Option<String> {
+        match self {
+            DiffSource::Staged(paths) => {
+                Some(staged_diff_stat(paths)).filter(|stat| !stat.is_empty())
+            }
+            _ => patch_stat(diff),
+        }
+    }
+}
+
+/// How many recent commits `--include-context` shows the model.
+pub const RECENT_COMMITS: u8 = 5;
+
+/// The diff together with what the repository says about where it is going:
+/// the branch, the repository's name and the latest commits.
+#[derive(Debug, Clone, Default)]
+pub struct GitContext {
+    pub diff: String,
+    /// `None` outside a repository or on a detached HEAD.
+    pub branch: Option<String>,
+    /// The name of the repository's top-level directory.
+    pub repo_name: Option<String>,
+    /// `git log --oneline` lines, newest first.
+    pub recent_log: Vec<String>,
+}
+
+impl GitContext {
+    /// Reads the diff from `source` and looks up the rest in the current
+    /// repository. Outside one, only the diff is filled in.
+    pub fn from_repo(
+        source: &DiffSource,
+        max_log_entries: u8,
+    ) -> Result<GitContext, AiCommitError> {
+        let recent_log = match max_log_entries {
+            0 => Vec::new(),
+            entries => git_output(&["log", "--oneline", &format!("-{}", entries)])
+                .map(|log| log.lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+        };
+        Ok(GitContext {
+            diff: source.read()?,
+            branch: git_output(&["rev-parse", "--abbrev-ref", "HEAD"])
+                .filter(|branch| branch != "HEAD"),
+            repo_name: repo_root().and_then(|root| {
+                root.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            }),
+            recent_log,
+        })
+    }
+
+    /// A paragraph for the system prompt, or `None` if nothing is known.
+    pub fn describe(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(repo) = &self.repo_name {
+            parts.push(format!("The repository is {}.", repo));
+        }
+        if let Some(branch) = &self.branch {
+            parts.push(format!("The changes are on the branch {}.", branch));
+        }
+        if !self.recent_log.is_empty() {
+            parts.push(format!(
+                "Recent commits, newest first, for context and style:\n{}",
+                self.recent_log.join("\n")
+            ));
+        }
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+}
+
+/// Trimmed stdout of `git args`, or `None` if it fails or prints nothing.
+fn git_output(args: &[&str]) -> Option<String> {
+    let text = run_git(args).ok()?.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// The top-level directory of the current repository, if inside one.
+pub fn repo_root() -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--show-toplevel")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!root.is_empty()).then(|| PathBuf::from(root))
+}
+
+/// Whether any staged file matches `pathspec`, e.g. `src/parser/**`.
+pub fn stages_any(pathspec: &str) -> bool {
+    git_output(&diff_args(
+        &["--staged", "--name-only"],
+        &[pathspec.to_string()],
+        &[],
+    ))
+    .is_some()
+}
+
+/// Whether any file matching `paths` has changes that aren't staged.
+pub fn has_unstaged_changes(paths: &[String]) -> bool {
+    git_output(&diff_args(&["--name-only"], paths, &[])).is_some()
+}
+
+/// `git diff` arguments: `options`, then `--`, then the pathspecs to include
+/// and to exclude. Everything after `--` is a path, even if it looks like an
+/// option.
+pub fn diff_args<'a>(
+    options: &[&'a str],
+    paths: &'a [String],
+    excludes: &'a [String],
+) -> Vec<&'a str> {
+    let mut args = vec!["diff"];
+    args.extend(options);
+    args.push("--");
+    args.extend(paths.iter().map(String::as_str));
+    args.extend(excludes.iter().map(String::as_str));
+    args
+}
+
+/// Arguments for `git commit`, or `git stash push` with `stash`, recording
+/// `message`. Non-empty `paths` limit it to those files.
+pub fn commit_args(message: &str, amend: bool, stash: bool, paths: &[String]) -> Vec<String> {
+    let mut args = if stash {
+        vec!["stash".to_string(), "push".to_string()]
+    } else {
+        vec!["commit".to_string()]
+    };
+    if amend {
+        args.push("--amend".to_string());
+    }
+    args.push("-m".to_string());
+    args.push(message.to_string());
+    if !paths.is_empty() {
+        args.push("--".to_string());
+        args.extend(paths.iter().cloned());
+    }
+    args
+}
+
+/// Lists files to leave out of the diff, in `.gitignore` syntax.
+pub const IGNORE_FILE: &str = ".aicommitignore";
+
+/// The patterns in `repo_root`'s `.aicommitignore`, without blank lines and
+/// comments. A missing file means no patterns.
+pub fn load_ignore_patterns(repo_root: &Path) -> Vec<String> {
+    let Ok(text) = fs::read_to_string(repo_root.join(IGNORE_FILE)) else {
+        return Vec::new();
+    };
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// `:(exclude)` pathspecs for `patterns`, relative to the repository root
+/// like `.gitignore`. A pattern without a slash matches at any depth, and
+/// every pattern also matches everything below a directory it names.
+/// Pathspecs can't re-include files, so `!` patterns are skipped.
+pub fn exclude_pathspecs(patterns: &[String]) -> Vec<String> {
+    let mut pathspecs = Vec::new();
+    for pattern in patterns {
+        if pattern.starts_with('!') {
+            debug!(
+                "{} can't re-include files; ignoring `{}`.",
+                IGNORE_FILE, pattern
+            );
+            continue;
+        }
+        let directory_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let glob = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{}", pattern),
+        };
+        if !directory_only {
+            pathspecs.push(format!(":(top,glob,exclude){}", glob));
+        }
+        pathspecs.push(format!(":(top,glob,exclude){}/**", glob));
+    }
+    pathspecs
+}
+
+/// Exclusions from the repository's `.aicommitignore`, if there is one.
+fn ignored_pathspecs() -> Vec<String> {
+    let patterns = repo_root()
+        .map(|root| load_ignore_patterns(&root))
+        .unwrap_or_default();
+    if !patterns.is_empty() {
+        debug!("Leaving out of the diff: {}", patterns.join(", "));
+    }
+    exclude_pathspecs(&patterns)
+}
+
+/// The full message of the last commit, if there is one.
+pub fn last_commit_message() -> Option<String> {
+    let output = Command::new("git")
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%B")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!message.is_empty()).then_some(message)
+}
+
+/// Diff from the parent of HEAD to the index. A root commit is compared
+/// with the empty tree.
+fn last_commit_diff() -> Result<String, AiCommitError> {
+    let base = match run_git(&["rev-parse", "--verify", "--quiet", "HEAD~1"]) {
+        Ok(_) => "HEAD~1".to_string(),
+        Err(_) => run_git(&["hash-object", "-t", "tree", "/dev/null"])?
+            .trim()
+            .to_string(),
+    };
+    let excludes = ignored_pathspecs();
+    run_git(&diff_args(&["--staged", &base], &[], &excludes))
+}
+
+/// `git diff --staged --shortstat` for `paths` (everything if empty), or an
+/// empty string if nothing is staged.
+fn staged_diff_stat(paths: &[String]) -> String {
+    let excludes = ignored_pathspecs();
+    git_output(&diff_args(&["--staged", "--shortstat"], paths, &excludes)).unwrap_or_default()
+}
+
+/// The summary line of `git apply --stat` for a patch that isn't in the
+/// index; `git apply` works outside a repository too.
+fn patch_stat(diff: &str) -> Option<String> {
+    let mut child = Command::new("git")
+        .args(["apply", "--stat", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    // Written from another thread so a large patch can't fill both pipes.
+    let mut stdin = child.stdin.take()?;
+    let diff = diff.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(diff.as_bytes()));
+    let output = child.wait_with_output().ok()?;
+    writer.join().ok()?.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stat = String::from_utf8_lossy(&output.stdout);
+    stat.lines()
+        .last()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+}
+
+fn staged_diff(paths: &[String]) -> Result<String, AiCommitError> {
+    let excludes = ignored_pathspecs();
+    run_git(&diff_args(&["--staged"], paths, &excludes))
+}
+
+/// Stdout of `git args`. Failing to start git, or git exiting with an
+/// error, is an `AiCommitError::Git` carrying git's own message.
+pub fn run_git(args: &[&str]) -> Result<String, AiCommitError> {
+    let command = args.first().copied().unwrap_or_default().to_string();
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| AiCommitError::Git {
+            command: command.clone(),
+            message: e.to_string(),
+        })?;
+    if !output.status.success() {
+        return Err(AiCommitError::Git {
+            command,
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn pathspecs_come_after_the_separator() {
+        let paths = strings(&["src/", "-odd-name.rs"]);
+        let excludes = strings(&[":(top,glob,exclude)**/*.lock"]);
+
+        assert_eq!(
+            diff_args(&["--staged"], &paths, &excludes),
+            [
+                "diff",
+                "--staged",
+                "--",
+                "src/",
+                "-odd-name.rs",
+                ":(top,glob,exclude)**/*.lock"
+            ]
+        );
+        assert_eq!(diff_args(&["HEAD"], &[], &[]), ["diff", "HEAD", "--"]);
+    }
+
+    #[test]
+    fn commit_paths_come_after_the_message_and_separator() {
+        assert_eq!(
+            commit_args("feat: x", false, false, &strings(&["a.rs", "-b.rs"])),
+            ["commit", "-m", "feat: x", "--", "a.rs", "-b.rs"]
+        );
+        assert_eq!(
+            commit_args("--amend", true, false, &[]),
+            ["commit", "--amend", "-m", "--amend"]
+        );
+        assert_eq!(
+            commit_args("wip", false, true, &strings(&["a.rs"])),
+            ["stash", "push", "-m", "wip", "--", "a.rs"]
+        );
+    }
+
+    #[test]
+    fn ignore_patterns_become_exclude_pathspecs() {
+        assert_eq!(
+            exclude_pathspecs(&strings(&["*.lock", "/dist/", "docs/gen", "!keep.lock"])),
+            [
+                ":(top,glob,exclude)**/*.lock",
+                ":(top,glob,exclude)**/*.lock/**",
+                ":(top,glob,exclude)dist/**",
+                ":(top,glob,exclude)docs/gen",
+                ":(top,glob,exclude)docs/gen/**",
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_file_skips_comments_and_blank_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_ignore_patterns(dir.path()).is_empty());
+
+        fs::write(
+            dir.path().join(IGNORE_FILE),
+            "# generated\n\n  *.lock  \nvendor/\n",
+        )
+        .unwrap();
+        assert_eq!(load_ignore_patterns(dir.path()), ["*.lock", "vendor/"]);
+    }
+}
@@ -0,0 +1,152 @@
+// ===================================================================
+// prepare-commit-msg Hook
+// ===================================================================
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::AiCommitError;
+
+/// First line after the shebang; marks hooks this tool may replace or remove.
+const MARKER: &str = "# Installed by ai_commit.";
+
+const HOOK_SCRIPT: &str = r#"#!/bin/sh
+# Installed by ai_commit. Remove with `ai_commit uninstall-hook`.
+#
+# Puts a generated message above git's usual comments. Commits that already
+# have a message (-m, -F, merges, squashes, amends) are left alone, and so is
+# a failed generation: the commit then goes ahead with an empty message.
+
+[ -z "$2" ] || exit 0
+git diff --cached --quiet && exit 0
+message=$(ai_commit generate --dry-run --quiet 2>/dev/null) || exit 0
+[ -n "$message" ] || exit 0
+{ printf '%s\n' "$message"; cat "$1"; } >"$1.ai_commit" && mv "$1.ai_commit" "$1"
+"#;
+
+/// Writes the hook into the current repository. An existing hook is only
+/// replaced with `force`.
+pub fn install_hook(force: bool) -> Result<PathBuf, AiCommitError> {
+    let path = hook_path()?;
+    write_hook(&path, force)?;
+    Ok(path)
+}
+
+fn write_hook(path: &Path, force: bool) -> Result<(), AiCommitError> {
+    if path.exists() && !force {
+        return Err(AiCommitError::Config(format!(
+            "{} already exists. Use --force to replace it.",
+            path.display()
+        )));
+    }
+    let write_error =
+        |e| AiCommitError::Config(format!("Failed to write {}: {}", path.display(), e));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(write_error)?;
+    }
+    fs::write(path, HOOK_SCRIPT).map_err(write_error)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(write_error)?;
+    }
+    Ok(())
+}
+
+/// Removes the hook, unless it was not written by `install_hook`.
+pub fn uninstall_hook() -> Result<PathBuf, AiCommitError> {
+    let path = hook_path()?;
+    remove_hook(&path)?;
+    Ok(path)
+}
+
+fn remove_hook(path: &Path) -> Result<(), AiCommitError> {
+    let script = fs::read_to_string(path).map_err(|_| {
+        AiCommitError::ConfigLookup(format!("No hook is installed at {}.", path.display()))
+    })?;
+    if !script.contains(MARKER) {
+        return Err(AiCommitError::Config(format!(
+            "{} was not installed by ai_commit; leaving it in place.",
+            path.display()
+        )));
+    }
+    fs::remove_file(path)
+        .map_err(|e| AiCommitError::Config(format!("Failed to remove {}: {}", path.display(), e)))
+}
+
+/// Where git looks for the hook, honouring `core.hooksPath` and worktrees.
+fn hook_path() -> Result<PathBuf, AiCommitError> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--git-path")
+        .arg("hooks/prepare-commit-msg")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .ok_or_else(|| AiCommitError::Config("Not inside a git repository.".to_string()))?;
+    Ok(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook_in(dir: &tempfile::TempDir) -> PathBuf {
+        dir.path().join("hooks").join("prepare-commit-msg")
+    }
+
+    #[test]
+    fn installed_hook_is_marked_and_runs_a_quiet_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hook_in(&dir);
+        write_hook(&path, false).unwrap();
+
+        let script = fs::read_to_string(&path).unwrap();
+        let mut lines = script.lines();
+        assert_eq!(lines.next(), Some("#!/bin/sh"));
+        assert!(lines.next().unwrap().starts_with(MARKER));
+        assert!(script.contains("[ -z \"$2\" ] || exit 0"));
+        assert!(script.contains("ai_commit generate --dry-run --quiet"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn existing_hooks_are_only_replaced_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hook_in(&dir);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "#!/bin/sh\necho mine\n").unwrap();
+
+        assert!(write_hook(&path, false).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#!/bin/sh\necho mine\n");
+        write_hook(&path, true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), HOOK_SCRIPT);
+    }
+
+    #[test]
+    fn uninstall_only_removes_hooks_with_the_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = hook_in(&dir);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        fs::write(&path, "#!/bin/sh\necho mine\n").unwrap();
+        assert!(matches!(remove_hook(&path), Err(AiCommitError::Config(_))));
+        assert!(path.exists());
+
+        write_hook(&path, true).unwrap();
+        remove_hook(&path).unwrap();
+        assert!(!path.exists());
+        assert!(matches!(
+            remove_hook(&path),
+            Err(AiCommitError::ConfigLookup(_))
+        ));
+    }
+}
@@ -0,0 +1,83 @@
+// ===================================================================
+// Language Tags
+// ===================================================================
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::output;
+
+/// Common BCP-47 tags for commit message languages, with their English names.
+pub const KNOWN_LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("bg", "Bulgarian"),
+    ("bn", "Bengali"),
+    ("ca", "Catalan"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("en-GB", "English (United Kingdom)"),
+    ("en-US", "English (United States)"),
+    ("es", "Spanish"),
+    ("es-MX", "Spanish (Mexico)"),
+    ("et", "Estonian"),
+    ("fa", "Persian"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("fr-CA", "French (Canada)"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hr", "Croatian"),
+    ("hu", "Hungarian"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("lt", "Lithuanian"),
+    ("lv", "Latvian"),
+    ("ms", "Malay"),
+    ("nb", "Norwegian Bokmål"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("pt-BR", "Portuguese (Brazil)"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sk", "Slovak"),
+    ("sl", "Slovenian"),
+    ("sr", "Serbian"),
+    ("sv", "Swedish"),
+    ("th", "Thai"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("vi", "Vietnamese"),
+    ("zh", "Chinese"),
+    ("zh-Hans", "Chinese (Simplified)"),
+    ("zh-Hant", "Chinese (Traditional)"),
+];
+
+/// A bare language (`xx`) or a language and region (`xx-XX`).
+static TAG_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z]{2}(-[A-Z]{2})?$").unwrap());
+
+/// Whether `tag` is a known tag, or at least shaped like one. Anything else
+/// is still passed to the model, which often copes with e.g. "Spanish".
+pub fn validate_language(tag: &str) -> bool {
+    KNOWN_LANGUAGES
+        .iter()
+        .any(|(known, _)| known.eq_ignore_ascii_case(tag))
+        || TAG_PATTERN.is_match(tag)
+}
+
+/// Prints a warning on stderr if `tag` does not look like a language tag.
+pub fn warn_if_unknown(tag: &str) {
+    if !validate_language(tag) {
+        output::warn(format!(
+            "Warning: '{}' is not a recognised language tag (see `ai_commit language list`); using it as is.",
+            tag
+        ));
+    }
+}
@@ -1,147 +1,1870 @@
 pub mod ai_commit;
+pub mod cache;
 pub mod cli;
 pub mod config;
+pub mod cost;
+pub mod doctor;
+pub mod error;
+pub mod git;
+pub mod hook;
+pub mod language;
+pub mod output;
+pub mod progress;
+pub mod prompt;
+pub mod providers;
+pub mod stats;
+pub mod structured;
+pub mod template;
+pub mod throttle;
 
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
-use std::io::Write;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use crate::cli::{Cli, ConfigCmd};
-use crate::config::{Config, get_config_path};
+use tracing::debug;
 
-pub async fn run_generate_commit(args: Cli, config: Config) {
-    let api_key = match config.api_key {
-        Some(key) => key,
-        None => {
-            eprintln!("API key not set. Please run `ai_commit config set-api-key <YOUR_KEY>`");
-            return;
+use crate::ai_commit::{
+    CommitFormat, Generation, GenerationOptions, MAX_STOP_SEQUENCES, MessageShape, TokenUsage,
+};
+
+use crate::cli::{ConfigCmd, GenerateArgs, Output, check_range, check_temperature};
+use crate::config::{
+    Config, KEYCHAIN_SENTINEL, SECRET_KEYS, back_up_config, expand_env_vars, get_config_path,
+    get_local_config_path, init_local_config, load_merged_config,
+};
+use crate::error::AiCommitError;
+use crate::git::{DiffSource, GitContext};
+use crate::progress::Spinner;
+use crate::providers::{
+    ApiStyle, ChatRequest, DEFAULT_TIMEOUT, Endpoint, HttpSettings, Provider, check_proxy,
+    check_proxy_env, redact_password, url_host,
+};
+
+pub async fn run_generate_commit(args: GenerateArgs, config: Config) -> Result<(), AiCommitError> {
+    let (provider, opts) = resolve_options(&args, &config)?;
+    match generate_and_commit(args, &config, provider, &opts).await {
+        Err(err) if is_model_not_found(&err) => Err(suggest_models(err, provider, &opts).await),
+        result => result,
+    }
+}
+
+async fn generate_and_commit(
+    args: GenerateArgs,
+    config: &Config,
+    provider: Provider,
+    opts: &GenerationOptions,
+) -> Result<(), AiCommitError> {
+    let out = output::printer();
+    let source = match args.diff_file {
+        Some(path) => DiffSource::File(path),
+        None if args.stdin => DiffSource::from_stdin()?,
+        None if args.amend => DiffSource::LastCommit,
+        None if args.for_stash => DiffSource::Unstaged,
+        None => DiffSource::Staged(staged_pathspecs(
+            &args.files,
+            args.commit && !(args.dry_run || args.msg),
+        )?),
+    };
+    let wants_branch = opts
+        .prompt_template
+        .as_deref()
+        .is_some_and(|template| template.contains("{branch}"));
+    let context = if opts.include_context || opts.include_branch || wants_branch {
+        GitContext::from_repo(&source, git::RECENT_COMMITS)?
+    } else {
+        GitContext {
+            diff: source.read()?,
+            ..GitContext::default()
+        }
+    };
+    let repo_context = if opts.include_context {
+        context.describe()
+    } else if opts.include_branch {
+        match &context.branch {
+            Some(branch) => Some(format!("The current branch is: {}", branch)),
+            None => {
+                debug!("Not on a branch (detached HEAD?), so the prompt doesn't name one.");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let diff_stat = opts
+        .stat_prefix
+        .then(|| source.stat(&context.diff))
+        .flatten();
+    let opts = &GenerationOptions {
+        repo_context,
+        branch: context.branch,
+        diff_stat,
+        ..opts.clone()
+    };
+    let mut diff = context.diff;
+    if diff.trim().is_empty() {
+        return match source {
+            DiffSource::Staged(_) => {
+                out.info("No staged changes to commit.");
+                Ok(())
+            }
+            DiffSource::LastCommit => {
+                out.info("No commit to amend.");
+                Ok(())
+            }
+            DiffSource::Unstaged => {
+                out.info("No local changes to stash.");
+                Ok(())
+            }
+            DiffSource::Stdin(_) => Err(AiCommitError::Config(
+                "The diff read from stdin is empty.".to_string(),
+            )),
+            DiffSource::File(path) => Err(AiCommitError::Config(format!(
+                "The diff file {} is empty.",
+                path.display()
+            ))),
+        };
+    }
+
+    if let Some(max_bytes) = args.max_diff_size.or(config.max_diff_size)
+        && diff.len() > max_bytes
+    {
+        progress::note(&format!(
+            "Diff is {} bytes, shortening it to fit --max-diff-size {}.",
+            diff.len(),
+            max_bytes
+        ));
+        diff = ai_commit::truncate_diff(&diff, max_bytes);
+    }
+
+    // Cheaper than a 400 from the API, and the model learns which files it
+    // isn't seeing.
+    let fitted = prompt::fit_diff(&diff, prompt::diff_budget(opts));
+    if fitted.diff.len() < diff.len() {
+        progress::note(&format!(
+            "Diff is too large for {}'s context window; left out ~{} tokens{}.",
+            opts.model,
+            fitted.omitted_tokens,
+            if fitted.omitted.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", fitted.omitted.join(", "))
+            }
+        ));
+        diff = fitted.diff;
+    }
+
+    let count = args.count.or(config.count).unwrap_or(1).max(1);
+
+    let json = args.output == Output::Json;
+    let show_usage = args.show_usage
+        || config.show_usage.unwrap_or(false)
+        || tracing::enabled!(tracing::Level::DEBUG);
+
+    // Rerunning on an unchanged diff reuses the message instead of paying for
+    // a new one. Only single messages are cached.
+    let cache_ttl = config
+        .cache_ttl_secs
+        .map_or(cache::DEFAULT_TTL, Duration::from_secs);
+    let cache_key = (count == 1 && !args.no_cache && !cache_ttl.is_zero())
+        .then(|| cache::key(&opts.chat_request(&diff), provider, &opts.url));
+    let cached = cache_key
+        .as_deref()
+        .and_then(|key| cache::get(key, cache_ttl));
+    if cached.is_none() {
+        check_cost(
+            &opts.chat_request(&diff),
+            config,
+            args.force,
+            args.show_cost,
+        )?;
+    }
+
+    let spinner = Spinner::start(cached.is_some());
+    // Streaming several candidates at once would interleave them, so --count
+    // takes precedence. JSON is printed in one piece once the reply is in.
+    let generation = if let Some(entry) = cached {
+        progress::note(&format!(
+            "Using the message cached {} min ago; pass --no-cache for a new one.",
+            entry.age() / 60
+        ));
+        let generation = Generation {
+            message: entry.message,
+            model: entry.model,
+            usage: None,
+            truncated: false,
+            cut_off: false,
+            latency: None,
+        };
+        if args.dry_run || args.msg {
+            print_generation(&generation, provider, json);
+            if args.copy {
+                copy_to_clipboard(&generation.message);
+            }
+            return Ok(());
+        }
+        generation
+    } else if opts.stream && count == 1 && !json {
+        let generation = ai_commit::stream_commit_message(&diff, provider, opts, |delta| {
+            spinner.stop();
+            print!("{}", out.message(delta));
+            io::stdout().flush().ok();
+        })
+        .await?;
+        spinner.stop();
+        println!();
+        record_stats([&generation], provider, &opts.language);
+        cache_generation(cache_key.as_deref(), &generation, cache_ttl);
+        note_incomplete([&generation], json);
+        if show_usage {
+            report_usage([&generation]);
+        }
+        if args.show_cost {
+            report_cost([&generation], config);
+        }
+        // Already printed as it streamed in.
+        if args.dry_run || args.msg {
+            if args.copy {
+                copy_to_clipboard(&generation.message);
+            }
+            return Ok(());
+        }
+        generation
+    } else if count > 1 {
+        let candidates = ai_commit::generate_commit_messages(&diff, provider, opts, count).await?;
+        spinner.stop();
+        record_stats(&candidates, provider, &opts.language);
+        note_incomplete(&candidates, json);
+        if show_usage {
+            report_usage(&candidates);
+        }
+        if args.show_cost {
+            report_cost(&candidates, config);
+        }
+        // Without a terminal there is nobody to ask, and JSON output is for
+        // scripts, so just list them.
+        if args.dry_run || args.msg || json || !io::stdout().is_terminal() {
+            if json {
+                let objects: Vec<_> = candidates
+                    .iter()
+                    .map(|candidate| generation_json(candidate, provider))
+                    .collect();
+                println!("{}", serde_json::Value::Array(objects));
+            } else {
+                let messages: Vec<String> = candidates
+                    .iter()
+                    .map(|candidate| out.message(&candidate.message))
+                    .collect();
+                println!("{}", messages.join("\n---\n"));
+            }
+            return Ok(());
+        }
+        match choose_candidate(candidates) {
+            Some(generation) => generation,
+            None => {
+                out.info("No message selected.");
+                return Ok(());
+            }
+        }
+    } else {
+        let generation = ai_commit::generate_commit_message(&diff, provider, opts).await?;
+        spinner.stop();
+        record_stats([&generation], provider, &opts.language);
+        cache_generation(cache_key.as_deref(), &generation, cache_ttl);
+        note_incomplete([&generation], json);
+        if show_usage {
+            report_usage([&generation]);
+        }
+        if args.show_cost {
+            report_cost([&generation], config);
         }
+        if args.dry_run || args.msg {
+            print_generation(&generation, provider, json);
+            if args.copy {
+                copy_to_clipboard(&generation.message);
+            }
+            return Ok(());
+        }
+        generation
     };
 
+    let no_edit = args.no_edit || (!args.edit && config.no_edit.unwrap_or(false));
+    let commit_message = if no_edit {
+        generation.message.clone()
+    } else {
+        open_in_editor(&generation.message, config.editor.as_deref())?
+    };
+    if args.copy {
+        copy_to_clipboard(&commit_message);
+    }
+    if !args.commit {
+        let edited = Generation {
+            message: commit_message,
+            ..generation
+        };
+        print_generation(&edited, provider, json);
+        return Ok(());
+    }
+    let command = if args.for_stash { "stash" } else { "commit" };
+    let paths = match &source {
+        DiffSource::Staged(paths) => paths.as_slice(),
+        _ => &[],
+    };
+    // git prints its own errors, so only the exit status is passed on.
+    let status = Command::new("git")
+        .args(git::commit_args(
+            &commit_message,
+            args.amend,
+            args.for_stash,
+            paths,
+        ))
+        .status()
+        .map_err(|e| AiCommitError::Git {
+            command: command.to_string(),
+            message: e.to_string(),
+        })?;
+    if !status.success() {
+        return Err(AiCommitError::Git {
+            command: command.to_string(),
+            message: format!("exited with {}", status),
+        });
+    }
+    Ok(())
+}
+
+/// The `--files` pathspecs that match staged files, warning about the rest.
+/// If none match, the returned pathspecs still select nothing, so the diff
+/// comes out empty rather than covering everything.
+///
+/// `git commit -- <paths>` takes the files as they are in the working tree,
+/// so with `commit` set, matching files may not have unstaged changes: the
+/// model would never see them.
+fn staged_pathspecs(files: &[String], commit: bool) -> Result<Vec<String>, AiCommitError> {
+    let (matched, unmatched): (Vec<String>, Vec<String>) = files
+        .iter()
+        .cloned()
+        .partition(|glob| git::stages_any(glob));
+    for glob in &unmatched {
+        output::warn(format!(
+            "Warning: --files {} matches no staged files.",
+            glob
+        ));
+    }
+    if matched.is_empty() {
+        return Ok(unmatched);
+    }
+    if commit && git::has_unstaged_changes(&matched) {
+        return Err(AiCommitError::Config(
+            "Files matching --files have unstaged changes, which `git commit -- <paths>` would \
+            commit without describing them. Stage or stash them first."
+                .to_string(),
+        ));
+    }
+    Ok(matched)
+}
+
+/// A request that failed because the endpoint does not know the model.
+fn is_model_not_found(err: &AiCommitError) -> bool {
+    match err {
+        AiCommitError::ApiError {
+            status: 400 | 404,
+            body,
+            ..
+        } => {
+            let body = body.to_lowercase();
+            body.contains("model_not_found")
+                || (body.contains("model")
+                    && (body.contains("not found") || body.contains("does not exist")))
+        }
+        _ => false,
+    }
+}
+
+/// Adds the closest model IDs offered by the endpoint to a model-not-found
+/// error. If the models can't be listed, the error is returned unchanged.
+async fn suggest_models(
+    err: AiCommitError,
+    provider: Provider,
+    opts: &GenerationOptions,
+) -> AiCommitError {
+    let AiCommitError::ApiError {
+        status,
+        body,
+        retry_after,
+    } = err
+    else {
+        return err;
+    };
+    let Ok(models) = provider.build(opts).list_models(&opts.http).await else {
+        return AiCommitError::ApiError {
+            status,
+            body,
+            retry_after,
+        };
+    };
+
+    let wanted = opts.model.to_lowercase();
+    // Allow roughly one typo per three characters.
+    let max_distance = (wanted.chars().count() / 3).max(2);
+    let mut close: Vec<(usize, String)> = models
+        .into_iter()
+        .map(|model| (edit_distance(&wanted, &model.to_lowercase()), model))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    close.sort();
+    let suggestions: Vec<String> = close.into_iter().take(3).map(|(_, model)| model).collect();
+    let body = if suggestions.is_empty() {
+        format!(
+            "{}\nModel '{}' not found; run `ai_commit models` to see what is available.",
+            body, opts.model
+        )
+    } else {
+        format!(
+            "{}\nModel '{}' not found; did you mean {}?",
+            body,
+            opts.model,
+            suggestions.join(" or ")
+        )
+    };
+    AiCommitError::ApiError {
+        status,
+        body,
+        retry_after,
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != *b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Prints the model IDs offered by the configured endpoint, optionally
+/// filtered by a case-insensitive substring.
+pub async fn list_models(
+    args: &GenerateArgs,
+    config: Config,
+    filter: Option<&str>,
+) -> Result<(), AiCommitError> {
+    let (provider, opts) = resolve_options(args, &config)?;
+    let mut models = provider.build(&opts).list_models(&opts.http).await?;
+    models.sort();
+    let filter = filter.map(str::to_lowercase);
+    for model in models {
+        if filter
+            .as_deref()
+            .is_none_or(|filter| model.to_lowercase().contains(filter))
+        {
+            println!("{}", model);
+        }
+    }
+    Ok(())
+}
+
+/// Merges CLI flags over config and provider defaults into the settings for
+/// one generation run.
+pub(crate) fn resolve_options(
+    args: &GenerateArgs,
+    config: &Config,
+) -> Result<(Provider, GenerationOptions), AiCommitError> {
     let language = args
         .language
-        .or(config.language)
+        .clone()
+        .or_else(|| config.language.clone())
         .unwrap_or_else(|| "en".to_string());
-    let prompt = args.prompt.or(config.prompt).unwrap_or_default();
-    let url = args
-        .url
-        .or(config.url)
-        .unwrap_or_else(|| "https://api.openai.com/v1/chat/completions".to_string());
+    language::warn_if_unknown(&language);
+    // Clap rejects --prompt together with --prompt-file.
+    let prompt_file = args
+        .prompt_file
+        .clone()
+        .or_else(|| config.prompt_file.as_ref().map(PathBuf::from))
+        .filter(|_| args.prompt.is_none());
+    let prompt = match prompt_file {
+        Some(path) => read_prompt_file(&path)?,
+        None => args
+            .prompt
+            .clone()
+            .or_else(|| config.prompt.clone())
+            .unwrap_or_default(),
+    };
+    let prompt_template = match args
+        .prompt_template_file
+        .clone()
+        .or_else(|| config.prompt_template_file.as_ref().map(PathBuf::from))
+    {
+        Some(path) => Some(read_prompt_file(&path)?),
+        None => config.prompt_template.clone(),
+    };
+    if let Some(template) = &prompt_template {
+        template::check(template)?;
+    }
+    let format = match args.format.as_deref().or(config.format.as_deref()) {
+        Some(name) => parse_format(name)?,
+        None => CommitFormat::Free,
+    };
+    let api_style = match config.api_style.as_deref() {
+        Some(name) => parse_api_style(name)?,
+        None => ApiStyle::Chat,
+    };
+    let temperature = match args.temperature {
+        Some(temperature) => Some(temperature),
+        None => config
+            .temperature
+            .map(check_temperature)
+            .transpose()
+            .map_err(AiCommitError::Config)?,
+    };
+    // Flags are checked while parsing; values from the config are checked here.
+    let sampling = |flag: Option<f32>, configured: Option<f32>, name, min, max| match flag {
+        Some(value) => Ok(Some(value)),
+        None => configured
+            .map(|value| check_range(name, value, min, max))
+            .transpose()
+            .map_err(AiCommitError::Config),
+    };
+    let top_p = sampling(args.top_p, config.top_p, "top_p", 0.0, 1.0)?;
+    let frequency_penalty = sampling(
+        args.frequency_penalty,
+        config.frequency_penalty,
+        "frequency_penalty",
+        -2.0,
+        2.0,
+    )?;
+    let presence_penalty = sampling(
+        args.presence_penalty,
+        config.presence_penalty,
+        "presence_penalty",
+        -2.0,
+        2.0,
+    )?;
+    let stop = if args.stop.is_empty() {
+        config.stop.clone().unwrap_or_default()
+    } else {
+        args.stop.clone()
+    };
+    if stop.len() > MAX_STOP_SEQUENCES {
+        return Err(AiCommitError::Config(format!(
+            "At most {} stop sequences are supported, got {}",
+            MAX_STOP_SEQUENCES,
+            stop.len()
+        )));
+    }
+    let region = resolve_region(config.region.clone());
+    let endpoint = Endpoint::resolve(
+        args.provider.as_deref().or(config.provider.as_deref()),
+        args.url.as_deref().or(config.url.as_deref()),
+        region.as_deref(),
+    )?;
+    let provider = endpoint.provider;
+    let seed = match args.seed.or(config.seed) {
+        Some(_) if !endpoint.seed => {
+            output::warn(format!(
+                "Warning: {} does not accept a seed; ignoring it.",
+                endpoint.name
+            ));
+            None
+        }
+        seed => seed,
+    };
+    let model_id = match provider {
+        Provider::Bedrock => config.model_id.clone(),
+        _ => None,
+    };
     let model = args
         .model
-        .or(config.model)
-        .unwrap_or_else(|| "gpt-3.5-turbo".to_string());
+        .clone()
+        .or(model_id)
+        .or_else(|| config.model.clone())
+        .unwrap_or_else(|| endpoint.default_model.to_string());
+    let model_settings = config.model_settings(&model).cloned().unwrap_or_default();
+    let max_tokens = match args
+        .max_tokens
+        .or(model_settings.max_output_tokens)
+        .or(config.max_tokens)
+    {
+        Some(0) => {
+            return Err(AiCommitError::Config(
+                "max_tokens must be at least 1".to_string(),
+            ));
+        }
+        max_tokens => max_tokens,
+    };
+    let scoped_key = config
+        .keys
+        .as_ref()
+        .and_then(|keys| scoped_api_key(keys, &endpoint));
+    // `api_key` first, then the `api_keys` list for rotation.
+    let general_keys: Vec<&String> = config
+        .api_key
+        .iter()
+        .chain(config.api_keys.iter().flatten())
+        .collect();
+    let api_key = match provider {
+        Provider::Anthropic => scoped_key
+            .or(config.anthropic_api_key.as_ref())
+            .or(general_keys.first().copied()),
+        _ => scoped_key.or(general_keys.first().copied()),
+    };
+    // Scoped and Anthropic-specific keys are never swapped for the general ones.
+    let api_keys = match api_key {
+        Some(key) if general_keys.first() == Some(&key) => general_keys[1..]
+            .iter()
+            .filter(|spare| **spare != key && spare.as_str() != KEYCHAIN_SENTINEL)
+            .map(|spare| spare.to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let env_key = endpoint.key_env.and_then(|var| env::var(var).ok());
+    let api_key = match api_key.or(env_key.as_ref()) {
+        Some(key) if key == KEYCHAIN_SENTINEL => {
+            return Err(AiCommitError::Credentials(
+                "The API key is stored in the OS keychain, but the keychain could not be read."
+                    .to_string(),
+            ));
+        }
+        Some(key) => key.clone(),
+        None if !provider.requires_api_key() => String::new(),
+        None => {
+            return Err(AiCommitError::Config(
+                "API key not set. Please run `ai_commit config set-api-key <YOUR_KEY>`".to_string(),
+            ));
+        }
+    };
 
-    let diff = get_git_diff();
-    if diff.is_empty() {
-        println!("No staged changes to commit.");
-        return;
+    let project = config
+        .project
+        .clone()
+        .or_else(|| env::var("GOOGLE_CLOUD_PROJECT").ok());
+    if provider == Provider::Vertex
+        && project.is_none()
+        && !endpoint.url.contains(":generateContent")
+    {
+        return Err(AiCommitError::Config(
+            "Google Cloud project not set. Please run `ai_commit config set project <PROJECT>` or set GOOGLE_CLOUD_PROJECT"
+                .to_string(),
+        ));
     }
 
-    match ai_commit::generate_commit_message(&diff, &api_key, &language, &prompt, &url, &model)
-        .await
-    {
-        Ok(commit_message) => {
-            if args.msg {
-                println!("{}", commit_message);
-            } else {
-                let commit_message = open_in_vim(&commit_message);
-                Command::new("git")
-                    .arg("commit")
-                    .arg("-m")
-                    .arg(commit_message)
-                    .status()
-                    .expect("Failed to commit");
+    let mut headers = endpoint.headers.clone();
+    if endpoint.name == Provider::OpenAi.name() {
+        if let Some(organization) = &config.organization {
+            headers.insert("OpenAI-Organization".to_string(), organization.clone());
+        }
+        if let Some(project) = &config.openai_project {
+            headers.insert("OpenAI-Project".to_string(), project.clone());
+        }
+    }
+    // Configured headers for the endpoint's host extend or replace the
+    // built-in ones; they are never sent to other hosts.
+    if let (Some(configured), Some(host)) = (&config.headers, url_host(&endpoint.url)) {
+        for (name, value) in configured.get(&host).into_iter().flatten() {
+            headers.insert(name.clone(), expand_env_vars(value)?);
+        }
+    }
+
+    // reqwest would only complain on the first request, or not at all for
+    // the environment variables.
+    let proxy = args.proxy.clone().or_else(|| config.proxy.clone());
+    match &proxy {
+        Some(proxy) => check_proxy(proxy)?,
+        None => check_proxy_env()?,
+    }
+
+    if config.insecure_skip_tls_verify == Some(true) {
+        output::warn(
+            "Warning: insecure_skip_tls_verify is set; TLS certificates are not being checked.",
+        );
+    }
+
+    // Flags win over the config, where the two settings can't be combined.
+    let shape = match (config.subject_only, config.with_body) {
+        // `git stash list` shows one line per stash.
+        _ if args.subject_only || args.for_stash => MessageShape::SubjectOnly,
+        _ if args.with_body => MessageShape::WithBody,
+        (Some(true), Some(true)) => {
+            return Err(AiCommitError::Config(
+                "subject_only and with_body can't both be enabled.".to_string(),
+            ));
+        }
+        (Some(true), _) => MessageShape::SubjectOnly,
+        (_, Some(true)) => MessageShape::WithBody,
+        _ => MessageShape::Any,
+    };
+
+    let structured = args.structured || config.structured.unwrap_or(false);
+    let mut opts = GenerationOptions {
+        api_key,
+        language,
+        prompt,
+        url: endpoint.url,
+        model,
+        azure_deployment: config.azure_deployment.clone(),
+        azure_api_version: config.azure_api_version.clone(),
+        region,
+        project,
+        // A JSON reply is no use to watch arriving.
+        stream: !structured && !args.no_stream && (args.stream || config.stream.unwrap_or(false)),
+        format,
+        structured,
+        gitmoji: args.gitmoji || config.use_gitmoji.unwrap_or(false),
+        max_retries: args.retries.or(config.max_retries).unwrap_or(3),
+        retry_delay: config
+            .retry_delay_ms
+            .map(Duration::from_millis)
+            .unwrap_or(ai_commit::RETRY_BASE_DELAY),
+        max_retry_wait: config
+            .max_retry_wait_secs
+            .map(Duration::from_secs)
+            .unwrap_or(ai_commit::MAX_RETRY_WAIT),
+        http: HttpSettings {
+            timeout: match args.timeout.or(config.timeout_secs) {
+                Some(0) => {
+                    return Err(AiCommitError::Config(
+                        "timeout_secs must be at least 1".to_string(),
+                    ));
+                }
+                Some(secs) => Duration::from_secs(secs),
+                None => DEFAULT_TIMEOUT,
+            },
+            proxy,
+            ca_cert: config.ca_cert_path.as_ref().map(PathBuf::from),
+            insecure: config.insecure_skip_tls_verify.unwrap_or(false),
+            requests_per_minute: config.requests_per_minute.filter(|_| !args.no_throttle),
+            ..HttpSettings::default()
+        },
+        api_style,
+        reasoning_model: config.reasoning_model,
+        headers,
+        temperature,
+        top_p,
+        frequency_penalty,
+        presence_penalty,
+        seed,
+        stop,
+        max_tokens,
+        context_window: model_settings.context_tokens.or(config.context_window),
+        api_keys,
+        chat_template: config.chat_template.clone(),
+        prompt_template,
+        branch: None,
+        include_context: args.include_context || config.include_context.unwrap_or(false),
+        include_branch: args.include_branch_context,
+        repo_context: None,
+        stat_prefix: !args.no_stat_prefix,
+        diff_stat: None,
+        previous_message: args.amend.then(git::last_commit_message).flatten(),
+        shape,
+        fallback_models: if args.fallback_models.is_empty() {
+            config.fallback_models.clone().unwrap_or_default()
+        } else {
+            args.fallback_models.clone()
+        },
+    };
+    let backend = provider.build(&opts);
+    if !opts.fallback_models.is_empty() && !backend.honours_model() {
+        output::warn(format!(
+            "Warning: ignoring fallback_models, as the {} URL or deployment decides the model.",
+            provider.name()
+        ));
+        opts.fallback_models.clear();
+    }
+    let taken = backend.sampling_settings();
+    let mut ignored = Vec::new();
+    for (name, set) in [
+        ("temperature", opts.temperature.is_some()),
+        ("top_p", opts.top_p.is_some()),
+        ("frequency_penalty", opts.frequency_penalty.is_some()),
+        ("presence_penalty", opts.presence_penalty.is_some()),
+        ("seed", opts.seed.is_some()),
+    ] {
+        if set && !taken.contains(&name) {
+            ignored.push(name);
+        }
+    }
+    if !ignored.is_empty() {
+        output::warn(format!(
+            "Warning: {} does not take {}; ignoring {}.",
+            provider.name(),
+            ignored.join(", "),
+            if ignored.len() == 1 { "it" } else { "them" }
+        ));
+        for name in ignored {
+            match name {
+                "temperature" => opts.temperature = None,
+                "top_p" => opts.top_p = None,
+                "frequency_penalty" => opts.frequency_penalty = None,
+                "presence_penalty" => opts.presence_penalty = None,
+                _ => opts.seed = None,
             }
         }
+    }
+    log_options(provider, &opts);
+    Ok((provider, opts))
+}
+
+/// Logs the settings a run ended up with, after flags, config and defaults
+/// are merged. Secrets are left out.
+fn log_options(provider: Provider, opts: &GenerationOptions) {
+    debug!(
+        "Provider: {}, model: {}, URL: {}",
+        provider.name(),
+        opts.model,
+        redact_password(&opts.url)
+    );
+    if !opts.fallback_models.is_empty() {
+        debug!("Fallback models: {}", opts.fallback_models.join(", "));
+    }
+    if !opts.stop.is_empty() {
+        debug!("Stop sequences: {:?}", opts.stop);
+    }
+    debug!(
+        "Language: {}, format: {:?}, structured: {}, shape: {:?}, gitmoji: {}, stream: {}",
+        opts.language, opts.format, opts.structured, opts.shape, opts.gitmoji, opts.stream
+    );
+    debug!(
+        "Sampling: temperature {:?}, top_p {:?}, frequency_penalty {:?}, presence_penalty {:?}, seed {:?}, max_tokens {:?}",
+        opts.temperature,
+        opts.top_p,
+        opts.frequency_penalty,
+        opts.presence_penalty,
+        opts.seed,
+        opts.max_tokens
+    );
+    debug!(
+        "Retries: {}, timeout: {:?}, API keys: {}",
+        opts.max_retries,
+        opts.http.timeout,
+        opts.api_keys.len() + usize::from(!opts.api_key.is_empty())
+    );
+}
+
+/// Tally of `config validate` results, printed as they are found.
+#[derive(Default)]
+struct Checks {
+    failures: usize,
+}
+
+impl Checks {
+    fn pass(&self, message: &str) {
+        println!("[ok]   {}", message);
+    }
+
+    fn warn(&self, message: &str) {
+        println!("[warn] {}", message);
+    }
+
+    fn fail(&mut self, message: &str) {
+        self.failures += 1;
+        println!("[fail] {}", message);
+    }
+
+    fn result(self) -> Result<(), AiCommitError> {
+        match self.failures {
+            0 => Ok(()),
+            1 => Err(AiCommitError::Config("1 check failed.".to_string())),
+            n => Err(AiCommitError::Config(format!("{} checks failed.", n))),
+        }
+    }
+}
+
+/// Checks the effective settings without sending a request (unless
+/// `check_api`), listing every problem found rather than stopping at the first.
+async fn validate_config(config: Config, check_api: bool) -> Result<(), AiCommitError> {
+    let mut checks = Checks::default();
+
+    // Validation ignores command-line overrides, so resolve against defaults.
+    let args = GenerateArgs::default();
+    let (provider, opts) = match resolve_options(&args, &config) {
+        Ok(resolved) => resolved,
         Err(e) => {
-            eprintln!("Error generating commit message:\n{}", e);
+            checks.fail(&e.to_string());
+            return checks.result();
         }
+    };
+
+    checks.pass(&format!("provider: {}", provider.name()));
+    match reqwest::Url::parse(&opts.url) {
+        Ok(url) => {
+            checks.pass(&format!("url: {}", opts.url));
+            let local = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+            if url.scheme() == "http" && !local {
+                checks.warn("url uses plain HTTP; diffs and the API key are sent unencrypted");
+            }
+        }
+        Err(e) => checks.fail(&format!("url '{}' is not valid: {}", opts.url, e)),
+    }
+    if !provider.requires_api_key() {
+        checks.pass("api key not required");
+    } else if opts.api_key.trim().is_empty() {
+        checks.fail("api key is empty");
+    } else {
+        checks.pass("api key is set");
+    }
+    if opts.model.trim().is_empty() {
+        checks.fail("model is empty");
+    } else {
+        checks.pass(&format!("model: {}", opts.model));
+    }
+
+    if check_api && checks.failures == 0 {
+        match provider.build(&opts).list_models(&opts.http).await {
+            Ok(models) => checks.pass(&format!("API reachable ({} models listed)", models.len())),
+            // No listing endpoint: fall back to a tiny generation request.
+            Err(AiCommitError::Config(_)) => {
+                let diff = "diff --git a/README b/README\n+ping\n";
+                match ai_commit::generate_commit_message(diff, provider, &opts).await {
+                    Ok(_) => checks.pass("API reachable (test request succeeded)"),
+                    Err(e) => checks.fail(&format!("API check failed: {}", e)),
+                }
+            }
+            Err(e) => checks.fail(&format!("API check failed: {}", e)),
+        }
+    }
+
+    checks.result()
+}
+
+/// Finds a key scoped to the endpoint: by provider or preset name, then by
+/// `host:port`, then by host.
+fn scoped_api_key<'a>(
+    keys: &'a BTreeMap<String, String>,
+    endpoint: &Endpoint,
+) -> Option<&'a String> {
+    if let Some(key) = keys.get(endpoint.name) {
+        return Some(key);
     }
+    let url = reqwest::Url::parse(&endpoint.url).ok()?;
+    let host = url.host_str()?;
+    url.port()
+        .and_then(|port| keys.get(&format!("{}:{}", host, port)))
+        .or_else(|| keys.get(host))
 }
 
-fn get_git_diff() -> String {
-    let output = Command::new("git")
-        .arg("diff")
-        .arg("--staged")
-        .output()
-        .expect("failed to execute git diff");
+/// Prints the message, or with `json` an object that also names the model,
+/// provider and tokens used.
+fn print_generation(generation: &Generation, provider: Provider, json: bool) {
+    if json {
+        println!("{}", generation_json(generation, provider));
+    } else {
+        println!("{}", output::printer().message(&generation.message));
+    }
+}
+
+/// Prints the model, the tokens used (summed over `generations`) and how
+/// long the request took to stderr, e.g.
+/// `gpt-4o-mini · 1,243 prompt + 38 completion tokens · 2.1s`.
+fn report_usage<'a>(generations: impl IntoIterator<Item = &'a Generation>) {
+    let generations: Vec<&Generation> = generations.into_iter().collect();
+    let Some(first) = generations.first() else {
+        return;
+    };
+    let mut parts = vec![first.model.clone()];
+    parts.push(
+        match TokenUsage::sum(generations.iter().map(|generation| generation.usage)) {
+            Some(usage) => format!(
+                "{} prompt + {} completion tokens",
+                thousands(usage.prompt_tokens),
+                thousands(usage.completion_tokens)
+            ),
+            None => "tokens not reported by the API".to_string(),
+        },
+    );
+    if let Some(latency) = first.latency {
+        parts.push(format!("{:.1}s", latency.as_secs_f64()));
+    }
+    eprintln!("{}", parts.join(" · "));
+}
 
-    String::from_utf8_lossy(&output.stdout).to_string()
+/// `1243` as `1,243`.
+fn thousands(n: u32) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
 }
 
-fn open_in_vim(commit_message: &str) -> String {
+/// Estimates the input cost of `request` from its size, prints it with
+/// `--show-cost`, and refuses to send it if that is over `max_cost`, unless
+/// `force` is set.
+fn check_cost(
+    request: &ChatRequest,
+    config: &Config,
+    force: bool,
+    show: bool,
+) -> Result<(), AiCommitError> {
+    let tokens = prompt::estimate_tokens(&request.system) + prompt::estimate_tokens(&request.user);
+    let Some(price) = cost::price(&request.model, config) else {
+        if show {
+            eprintln!("[Estimated cost: no price known for {}]", request.model);
+        }
+        if config.max_cost.is_some() && !force {
+            output::warn(format!(
+                "Warning: no price known for {}, so max_cost can't be checked. Set one with `config set-model-price`.",
+                request.model
+            ));
+        }
+        return Ok(());
+    };
+    let estimate = price.cost(tokens as u64, 0);
+    if show {
+        eprintln!(
+            "[Estimated input cost: {} for ~{} tokens]",
+            cost::format(estimate),
+            tokens
+        );
+    }
+    match config.max_cost {
+        Some(max_cost) if estimate > max_cost && !force => Err(AiCommitError::Config(format!(
+            "The estimated input cost of {} is over max_cost ({}). Pass --force to send it anyway.",
+            cost::format(estimate),
+            cost::format(max_cost)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Prints the cost of `generations` from the usage the API reported, to
+/// stderr.
+fn report_cost<'a>(generations: impl IntoIterator<Item = &'a Generation>, config: &Config) {
+    let generations: Vec<&Generation> = generations.into_iter().collect();
+    let Some(model) = generations.first().map(|generation| &generation.model) else {
+        return;
+    };
+    let usage = TokenUsage::sum(generations.iter().map(|generation| generation.usage));
+    match (usage, cost::price(model, config)) {
+        (Some(usage), Some(price)) => eprintln!(
+            "[Cost: {} for {}]",
+            cost::format(price.cost(usage.prompt_tokens.into(), usage.completion_tokens.into())),
+            usage
+        ),
+        (None, _) => eprintln!("[Cost: not known, as the API reported no usage]"),
+        (_, None) => eprintln!("[Cost: no price known for {}]", model),
+    }
+}
+
+/// Tells the user when the message is based on a diff with files left out,
+/// which JSON output carries in a `truncated` field instead, and warns when
+/// a reply was cut off by the token limit.
+fn note_incomplete<'a>(generations: impl IntoIterator<Item = &'a Generation>, json: bool) {
+    let (truncated, cut_off) =
+        generations
+            .into_iter()
+            .fold((false, false), |(truncated, cut_off), generation| {
+                (
+                    truncated || generation.truncated,
+                    cut_off || generation.cut_off,
+                )
+            });
+    if truncated && !json {
+        progress::note(
+            "Note: the diff was too large, so the message was generated from a truncated diff.",
+        );
+    }
+    if cut_off {
+        output::warn(
+            "Warning: the reply hit the token limit, so the message may be cut off. \
+            Raise the limit with --max-tokens.",
+        );
+    }
+}
+
+/// Adds `generations` to the usage statistics. A failure here only warns, as
+/// the message itself is fine.
+fn record_stats<'a>(
+    generations: impl IntoIterator<Item = &'a Generation>,
+    provider: Provider,
+    language: &str,
+) {
+    for generation in generations {
+        if let Err(e) = stats::record(generation, provider.name(), language) {
+            output::warn(format!("Warning: {}", e));
+            return;
+        }
+    }
+}
+
+/// Copies `message` to the system clipboard, warning rather than failing
+/// where there is none, e.g. over SSH without a display.
+fn copy_to_clipboard(message: &str) {
+    let copied =
+        arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(message.trim()));
+    match copied {
+        // On stderr, so stdout still carries only the message.
+        Ok(()) => progress::note("Copied the message to the clipboard."),
+        Err(e) => output::warn(format!("Warning: could not copy to the clipboard: {}", e)),
+    }
+}
+
+/// Caches a complete message under `key`, warning if that fails.
+fn cache_generation(key: Option<&str>, generation: &Generation, ttl: Duration) {
+    let Some(key) = key else {
+        return;
+    };
+    if generation.cut_off {
+        return;
+    }
+    if let Err(e) = cache::store(key, &generation.message, &generation.model, ttl) {
+        output::warn(format!("Warning: {}", e));
+    }
+}
+
+fn generation_json(generation: &Generation, provider: Provider) -> serde_json::Value {
+    serde_json::json!({
+        "message": generation.message,
+        "model": generation.model,
+        "provider": provider.name(),
+        "tokens_used": generation.usage.map(|usage| usage.total_tokens),
+        "usage": generation.usage,
+        "latency_ms": generation.latency.map(|latency| latency.as_millis() as u64),
+        "truncated": generation.truncated,
+    })
+}
+
+/// Lists the candidates and reads a 1-based choice from stdin. Returns `None`
+/// if stdin is closed before a valid choice is made.
+fn choose_candidate(mut candidates: Vec<Generation>) -> Option<Generation> {
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!(
+            "{}) {}\n",
+            i + 1,
+            output::printer().message(candidate.message.trim().replace('\n', "\n   "))
+        );
+    }
+
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("Choose a message [1-{}]: ", candidates.len());
+        io::stdout().flush().ok();
+        let line = lines.next()?.ok()?;
+        match line.trim().parse::<usize>() {
+            Ok(choice) if (1..=candidates.len()).contains(&choice) => {
+                return Some(candidates.swap_remove(choice - 1));
+            }
+            _ => println!("Please enter a number between 1 and {}.", candidates.len()),
+        }
+    }
+}
+
+/// Lets `config` subcommands, which are how a broken config gets fixed, run
+/// despite a `config.toml` that doesn't parse: after asking, the file is
+/// backed up to `config.toml.bak` and they start from an empty config.
+/// Without a terminal to ask on, or if the answer is no, `err` is returned.
+pub fn recover_broken_config(err: AiCommitError) -> Result<Config, AiCommitError> {
+    if !io::stdin().is_terminal() {
+        return Err(err);
+    }
+    let out = output::printer();
+    eprintln!("{}", out.error(format!("Configuration error: {}", err)));
+    eprint!("Back up config.toml to config.toml.bak and start with an empty config? [y/N] ");
+    io::stderr().flush().ok();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok();
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        return Err(err);
+    }
+    let backup = back_up_config()?;
+    out.info(format_args!("Moved the old config to {}", backup.display()));
+    Ok(Config::default())
+}
+
+/// The custom prompt or template stored in `path`, without trailing
+/// whitespace.
+fn read_prompt_file(path: &Path) -> Result<String, AiCommitError> {
+    fs::read_to_string(path)
+        .map(|prompt| prompt.trim_end().to_string())
+        .map_err(|e| {
+            AiCommitError::Config(format!(
+                "Failed to read prompt file {}: {}",
+                path.display(),
+                e
+            ))
+        })
+}
+
+fn parse_format(name: &str) -> Result<CommitFormat, AiCommitError> {
+    CommitFormat::from_name(name).ok_or_else(|| {
+        AiCommitError::Config(format!(
+            "Unknown format '{}'. Supported: free, conventional",
+            name
+        ))
+    })
+}
+
+fn parse_api_style(name: &str) -> Result<ApiStyle, AiCommitError> {
+    ApiStyle::from_name(name).ok_or_else(|| {
+        AiCommitError::Config(format!(
+            "Unknown API style '{}'. Supported: chat, completions",
+            name
+        ))
+    })
+}
+
+/// Whether `key`, or the table it is in, is one of the [`SECRET_KEYS`].
+fn is_secret_key(key: &str) -> bool {
+    let top = key.split('.').next().unwrap_or(key).replace('_', "-");
+    SECRET_KEYS.contains(&top.as_str())
+}
+
+/// Host of the endpoint the effective settings point at, which is where
+/// `config set-header` files a header by default.
+fn endpoint_host(config: &Config) -> Result<String, AiCommitError> {
+    let merged = load_merged_config(config.clone())?;
+    let region = resolve_region(merged.region.clone());
+    let endpoint = Endpoint::resolve(
+        merged.provider.as_deref(),
+        merged.url.as_deref(),
+        region.as_deref(),
+    )?;
+    url_host(&endpoint.url).ok_or_else(|| {
+        AiCommitError::Config(format!(
+            "Could not tell the host of {}; pass --host.",
+            endpoint.url
+        ))
+    })
+}
+
+/// The configured AWS region, falling back to the standard AWS env vars.
+fn resolve_region(configured: Option<String>) -> Option<String> {
+    configured
+        .or_else(|| env::var("AWS_REGION").ok())
+        .or_else(|| env::var("AWS_DEFAULT_REGION").ok())
+}
+
+/// Resolves the editor command: `$VISUAL`, then `$EDITOR`, then the configured
+/// editor, and finally `vim`.
+pub(crate) fn resolve_editor(configured: Option<&str>) -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .chain(configured.map(str::to_string))
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vim".to_string())
+}
+
+/// Set while the editor is open, when Ctrl-C is the editor's to handle.
+static IN_EDITOR: AtomicBool = AtomicBool::new(false);
+
+/// Set when Ctrl-C was pressed while the editor was open.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Takes over Ctrl-C for the rest of the run. Normally it aborts straight
+/// away with status 130, dropping any request in flight. While the editor is
+/// open it is left to the editor, and the commit is skipped once the editor
+/// exits.
+pub fn handle_interrupts() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if IN_EDITOR.load(Ordering::SeqCst) {
+                INTERRUPTED.store(true, Ordering::SeqCst);
+            } else {
+                progress::clear();
+                eprintln!("\n{}", output::printer().error(AiCommitError::Interrupted));
+                std::process::exit(AiCommitError::Interrupted.exit_code());
+            }
+        }
+    });
+}
+
+fn open_in_editor(
+    commit_message: &str,
+    configured_editor: Option<&str>,
+) -> Result<String, AiCommitError> {
     let mut temp_file = tempfile::Builder::new()
         .prefix("COMMIT_MSG_")
         .suffix(".txt")
         .tempfile()
-        .expect("Failed to create temporary file");
+        .map_err(|e| AiCommitError::Editor(format!("failed to create a temporary file: {}", e)))?;
+    write!(temp_file, "{}", commit_message)
+        .map_err(|e| AiCommitError::Editor(format!("failed to write the temporary file: {}", e)))?;
+    let temp_path = temp_file.path();
 
-    write!(temp_file, "{}", commit_message).expect("Failed to write to temporary file");
-    let temp_path = temp_file.path().to_str().unwrap();
+    // Editors like `code --wait` carry their own arguments.
+    let editor = resolve_editor(configured_editor);
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vim");
 
-    Command::new("vim")
-        .arg(temp_path)
-        .status()
-        .expect("Failed to open Vim");
+    IN_EDITOR.store(true, Ordering::SeqCst);
+    let status = Command::new(program).args(parts).arg(temp_path).status();
+    IN_EDITOR.store(false, Ordering::SeqCst);
+    let status =
+        status.map_err(|e| AiCommitError::Editor(format!("failed to open '{}': {}", editor, e)))?;
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(AiCommitError::Interrupted);
+    }
+    // Quitting with an error, like `:cq` in vim, abandons the message.
+    if !status.success() {
+        return Err(AiCommitError::Editor(match status.code() {
+            Some(code) => format!("'{}' exited with status {}; commit aborted", editor, code),
+            None => format!("'{}' was terminated by a signal; commit aborted", editor),
+        }));
+    }
 
-    fs::read_to_string(temp_path).expect("Failed to read from temporary file")
+    fs::read_to_string(temp_path)
+        .map_err(|e| AiCommitError::Editor(format!("failed to read the temporary file: {}", e)))
 }
 
-pub fn handle_config_command(cmd: ConfigCmd, mut config: Config) {
+pub async fn handle_config_command(
+    cmd: ConfigCmd,
+    mut config: Config,
+) -> Result<(), AiCommitError> {
+    let out = output::printer();
     match cmd {
-        ConfigCmd::SetApiKey { key } => {
+        ConfigCmd::SetApiKey {
+            key,
+            provider: Some(provider),
+        } => {
+            config
+                .keys
+                .get_or_insert_with(Default::default)
+                .insert(provider.clone(), key);
+            config.save_config()?;
+            out.info(format_args!("API key for {} set successfully.", provider));
+        }
+        ConfigCmd::SetApiKey {
+            key,
+            provider: None,
+        } => {
             config.api_key = Some(key);
-            config.save_config();
-            println!("API key set successfully.");
+            config.save_config()?;
+            out.info("API key set successfully.");
+        }
+        ConfigCmd::SetAnthropicApiKey { key } => {
+            config.anthropic_api_key = Some(key);
+            config.save_config()?;
+            out.info("Anthropic API key set successfully.");
+        }
+        ConfigCmd::SetUseKeychain { enabled } => {
+            config.use_keychain = Some(enabled);
+            // Saving moves any existing keys into (or out of) the keychain.
+            config.save_config()?;
+            out.info(format_args!("Keychain storage set to: {}", enabled));
         }
         ConfigCmd::SetUrl { url } => {
             config.url = Some(url);
-            config.save_config();
-            println!("API URL set to: {}", config.url.as_deref().unwrap());
+            config.save_config()?;
+            out.info(format_args!(
+                "API URL set to: {}",
+                config.url.as_deref().unwrap()
+            ));
         }
         ConfigCmd::SetModel { model } => {
             config.model = Some(model);
-            config.save_config();
-            println!("Default model set to: {}", config.model.as_deref().unwrap());
+            config.save_config()?;
+            out.info(format_args!(
+                "Default model set to: {}",
+                config.model.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetFallbackModels { models } => {
+            let list = models.join(", ");
+            config.fallback_models = Some(models);
+            config.save_config()?;
+            out.info(format_args!("Fallback models set to: {}", list));
         }
         ConfigCmd::SetLanguage { lang } => {
+            language::warn_if_unknown(&lang);
             config.language = Some(lang);
-            config.save_config();
-            println!(
+            config.save_config()?;
+            out.info(format_args!(
                 "Default language set to: {}",
                 config.language.as_deref().unwrap()
-            );
+            ));
         }
         ConfigCmd::SetPrompt { prompt } => {
             config.prompt = Some(prompt);
-            config.save_config();
-            println!("Default prompt set.");
+            config.save_config()?;
+            out.info("Default prompt set.");
+        }
+        ConfigCmd::SetProvider { provider } => {
+            if !crate::providers::is_known(&provider) {
+                return Err(AiCommitError::Config(format!(
+                    "Unknown provider '{}'. Supported: {}",
+                    provider,
+                    crate::providers::supported_names()
+                )));
+            }
+            config.provider = Some(provider);
+            config.save_config()?;
+            out.info(format_args!(
+                "Provider set to: {}",
+                config.provider.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetEditor { editor } => {
+            config.editor = Some(editor);
+            config.save_config()?;
+            out.info(format_args!(
+                "Editor set to: {}",
+                config.editor.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetAzureDeployment { deployment } => {
+            config.azure_deployment = Some(deployment);
+            config.save_config()?;
+            out.info(format_args!(
+                "Azure deployment set to: {}",
+                config.azure_deployment.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetAzureApiVersion { version } => {
+            config.azure_api_version = Some(version);
+            config.save_config()?;
+            out.info(format_args!(
+                "Azure API version set to: {}",
+                config.azure_api_version.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetOrganization { organization } => {
+            config.organization = Some(organization);
+            config.save_config()?;
+            out.info(format_args!(
+                "OpenAI organization set to: {}",
+                config.organization.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetOpenaiProject { project } => {
+            config.openai_project = Some(project);
+            config.save_config()?;
+            out.info(format_args!(
+                "OpenAI project set to: {}",
+                config.openai_project.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetRegion { region } => {
+            config.region = Some(region);
+            config.save_config()?;
+            out.info(format_args!(
+                "Region set to: {}",
+                config.region.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetModelId { model_id } => {
+            config.model_id = Some(model_id);
+            config.save_config()?;
+            out.info(format_args!(
+                "Model ID set to: {}",
+                config.model_id.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetStream { enabled } => {
+            config.stream = Some(enabled);
+            config.save_config()?;
+            out.info(format_args!("Streaming set to: {}", enabled));
+        }
+        ConfigCmd::SetGitmoji { enabled } => {
+            config.use_gitmoji = Some(enabled);
+            config.save_config()?;
+            out.info(format_args!("Gitmoji set to: {}", enabled));
+        }
+        ConfigCmd::SetNoEdit { enabled } => {
+            config.no_edit = Some(enabled);
+            config.save_config()?;
+            out.info(format_args!("Skip editor set to: {}", enabled));
+        }
+        ConfigCmd::SetWithBody { enabled } => {
+            config.with_body = Some(enabled);
+            config.save_config()?;
+            out.info(format_args!("Subject and body set to: {}", enabled));
+        }
+        ConfigCmd::SetFormat { format } => {
+            parse_format(&format)?;
+            config.format = Some(format);
+            config.save_config()?;
+            out.info(format_args!(
+                "Format set to: {}",
+                config.format.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetMaxRetries { retries } => {
+            config.max_retries = Some(retries);
+            config.save_config()?;
+            out.info(format_args!("Max retries set to: {}", retries));
+        }
+        ConfigCmd::SetProxy { proxy } => {
+            check_proxy(&proxy)?;
+            config.proxy = Some(proxy.clone());
+            config.save_config()?;
+            out.info(format_args!("Proxy set to: {}", redact_password(&proxy)));
+        }
+        ConfigCmd::SetTimeout { secs } => {
+            config.timeout_secs = Some(secs);
+            config.save_config()?;
+            out.info(format_args!("Timeout set to: {}s", secs));
+        }
+        ConfigCmd::SetApiStyle { style } => {
+            parse_api_style(&style)?;
+            config.api_style = Some(style);
+            config.save_config()?;
+            out.info(format_args!(
+                "API style set to: {}",
+                config.api_style.as_deref().unwrap()
+            ));
+        }
+        ConfigCmd::SetCount { count } => {
+            config.count = Some(count);
+            config.save_config()?;
+            out.info(format_args!("Candidate count set to: {}", count));
+        }
+        ConfigCmd::SetTemperature { temperature } => {
+            config.temperature = Some(temperature);
+            config.save_config()?;
+            out.info(format_args!("Temperature set to: {}", temperature));
+        }
+        ConfigCmd::SetMaxTokens { max_tokens } => {
+            config.max_tokens = Some(max_tokens);
+            config.save_config()?;
+            out.info(format_args!("Max tokens set to: {}", max_tokens));
+        }
+        ConfigCmd::SetModelContext {
+            model,
+            tokens,
+            max_output_tokens,
+        } => {
+            let settings = config
+                .models
+                .get_or_insert_with(Default::default)
+                .entry(model.clone())
+                .or_default();
+            settings.context_tokens = Some(tokens as usize);
+            if max_output_tokens.is_some() {
+                settings.max_output_tokens = max_output_tokens;
+            }
+            config.save_config()?;
+            out.info(format_args!(
+                "Context window of {} set to: {}",
+                model, tokens
+            ));
+        }
+        ConfigCmd::SetModelPrice {
+            model,
+            input,
+            output,
+        } => {
+            let settings = config
+                .models
+                .get_or_insert_with(Default::default)
+                .entry(model.clone())
+                .or_default();
+            settings.input_price = Some(input);
+            settings.output_price = Some(output);
+            config.save_config()?;
+            out.info(format_args!(
+                "Price of {} set to: ${} input, ${} output per million tokens",
+                model, input, output
+            ));
+        }
+        ConfigCmd::SetReasoningModel { enabled } => {
+            config.reasoning_model = Some(enabled);
+            config.save_config()?;
+            out.info(format_args!("Reasoning model mode set to: {}", enabled));
+        }
+        ConfigCmd::SetHeader { name, value, host } => {
+            let host = match host {
+                Some(host) => host,
+                None => endpoint_host(&config)?,
+            };
+            config
+                .set(&format!("headers.{}.{}", host, name), &value)?
+                .save_config()?;
+            out.info(format_args!("Header {} set for {}.", name, host));
+        }
+        ConfigCmd::UnsetHeader { name, host } => {
+            let host = match host {
+                Some(host) => host,
+                None => endpoint_host(&config)?,
+            };
+            config
+                .unset(&format!("headers.{}.{}", host, name))?
+                .save_config()?;
+            out.info(format_args!("Header {} unset for {}.", name, host));
+        }
+        ConfigCmd::Set { key, value } => {
+            config.set(&key, &value)?.save_config()?;
+            out.info(format_args!("{} set.", key));
+        }
+        ConfigCmd::Get { key } => {
+            let value = load_merged_config(config)?
+                .get(&key)
+                .map_err(|e| AiCommitError::ConfigLookup(e.to_string()))?;
+            match value {
+                Some(_) if is_secret_key(&key) => {
+                    println!("{}", out.value("[set]"))
+                }
+                Some(toml::Value::String(value)) if key == "proxy" => {
+                    println!("{}", out.value(redact_password(&value)))
+                }
+                Some(toml::Value::String(value)) => println!("{}", out.value(value)),
+                Some(value) => println!("{}", out.value(value)),
+                None => {
+                    return Err(AiCommitError::ConfigLookup(format!("{} is not set.", key)));
+                }
+            }
+        }
+        ConfigCmd::Unset { key } => {
+            config.unset(&key)?.save_config()?;
+            out.info(format_args!("Unset {}.", key));
+        }
+        ConfigCmd::Validate { check_api } => {
+            validate_config(load_merged_config(config)?, check_api).await?;
+        }
+        ConfigCmd::Init => {
+            let path = init_local_config()?;
+            out.info(format_args!("Created {}", path.display()));
+        }
+        ConfigCmd::Trust => {
+            let root = git::repo_root()
+                .ok_or_else(|| AiCommitError::Config("Not inside a git repository.".to_string()))?;
+            if !config.trusts(&root) {
+                config
+                    .trusted_repos
+                    .get_or_insert_with(Vec::new)
+                    .push(root.display().to_string());
+                config.save_config()?;
+            }
+            out.info(format_args!("Trusted {}", root.display()));
         }
         ConfigCmd::Show => {
             println!(
                 "Current configuration file path: {}",
-                get_config_path().display()
+                get_config_path()?.display()
             );
+            if let Some(local_path) = get_local_config_path().filter(|path| path.exists()) {
+                println!("Repository overrides: {}", local_path.display());
+            }
             println!("---");
             if let Some(_api_key) = &config.api_key {
-                println!("api_key = [set]");
+                println!("api_key = {}", out.value("[set]"));
             } else {
-                println!("api_key = [not set]");
+                println!("api_key = {}", out.value("[not set]"));
+            }
+            if let Some(api_keys) = &config.api_keys {
+                println!(
+                    "api_keys = {}",
+                    out.value(format!("[{} set]", api_keys.len()))
+                );
+            }
+            if config.anthropic_api_key.is_some() {
+                println!("anthropic_api_key = {}", out.value("[set]"));
+            }
+            for name in config.keys.iter().flat_map(|keys| keys.keys()) {
+                println!("keys.{} = {}", name, out.value("[set]"));
+            }
+            for (host, headers) in config.headers.iter().flatten() {
+                for name in headers.keys() {
+                    println!("headers.{}.{} = {}", host, name, out.value("[set]"));
+                }
+            }
+            if let Some(template) = &config.chat_template {
+                println!("chat_template = {}", out.value(format!("{:?}", template)));
+            }
+            if let Some(use_keychain) = config.use_keychain {
+                println!("use_keychain = {}", out.value(use_keychain));
             }
             if let Some(url) = &config.url {
-                println!("url = \"{}\"", url);
+                println!("url = {}", out.value(format!("\"{}\"", url)));
+            }
+            match Endpoint::resolve(
+                config.provider.as_deref(),
+                config.url.as_deref(),
+                resolve_region(config.region.clone()).as_deref(),
+            ) {
+                Ok(endpoint) => println!("# effective url: {}", endpoint.url),
+                Err(e) => println!("# effective url: unavailable ({})", e),
             }
             if let Some(model) = &config.model {
-                println!("model = \"{}\"", model);
+                println!("model = {}", out.value(format!("\"{}\"", model)));
+            }
+            if let Some(models) = &config.fallback_models {
+                println!("fallback_models = {}", out.value(format!("{:?}", models)));
             }
             if let Some(language) = &config.language {
-                println!("language = \"{}\"", language);
+                println!("language = {}", out.value(format!("\"{}\"", language)));
             }
             if let Some(prompt) = &config.prompt {
-                println!("prompt = \"{}\"", prompt);
+                println!("prompt = {}", out.value(format!("\"{}\"", prompt)));
+            }
+            if let Some(path) = &config.prompt_file {
+                println!("prompt_file = {}", out.value(format!("\"{}\"", path)));
+            }
+            if let Some(template) = &config.prompt_template {
+                println!("prompt_template = {}", out.value(format!("{:?}", template)));
+            }
+            if let Some(path) = &config.prompt_template_file {
+                println!(
+                    "prompt_template_file = {}",
+                    out.value(format!("\"{}\"", path))
+                );
+            }
+            if let Some(include) = config.include_context {
+                println!("include_context = {}", out.value(include));
+            }
+            if let Some(provider) = &config.provider {
+                println!("provider = {}", out.value(format!("\"{}\"", provider)));
+            }
+            if let Some(editor) = &config.editor {
+                println!("editor = {}", out.value(format!("\"{}\"", editor)));
+            }
+            if let Some(organization) = &config.organization {
+                println!(
+                    "organization = {}",
+                    out.value(format!("\"{}\"", organization))
+                );
+            }
+            if let Some(project) = &config.openai_project {
+                println!("openai_project = {}", out.value(format!("\"{}\"", project)));
+            }
+            if let Some(deployment) = &config.azure_deployment {
+                println!(
+                    "azure_deployment = {}",
+                    out.value(format!("\"{}\"", deployment))
+                );
+            }
+            if let Some(version) = &config.azure_api_version {
+                println!(
+                    "azure_api_version = {}",
+                    out.value(format!("\"{}\"", version))
+                );
+            }
+            if let Some(region) = &config.region {
+                println!("region = {}", out.value(format!("\"{}\"", region)));
+            }
+            if let Some(model_id) = &config.model_id {
+                println!("model_id = {}", out.value(format!("\"{}\"", model_id)));
+            }
+            if let Some(project) = &config.project {
+                println!("project = {}", out.value(format!("\"{}\"", project)));
+            }
+            if let Some(stream) = config.stream {
+                println!("stream = {}", out.value(stream));
+            }
+            if let Some(format) = &config.format {
+                println!("format = {}", out.value(format!("\"{}\"", format)));
+            }
+            if let Some(structured) = config.structured {
+                println!("structured = {}", out.value(structured));
+            }
+            if let Some(gitmoji) = config.use_gitmoji {
+                println!("use_gitmoji = {}", out.value(gitmoji));
+            }
+            if let Some(show_usage) = config.show_usage {
+                println!("show_usage = {}", out.value(show_usage));
+            }
+            if let Some(subject_only) = config.subject_only {
+                println!("subject_only = {}", out.value(subject_only));
+            }
+            if let Some(with_body) = config.with_body {
+                println!("with_body = {}", out.value(with_body));
+            }
+            if let Some(no_edit) = config.no_edit {
+                println!("no_edit = {}", out.value(no_edit));
+            }
+            if let Some(retries) = config.max_retries {
+                println!("max_retries = {}", out.value(retries));
+            }
+            if let Some(delay) = config.retry_delay_ms {
+                println!("retry_delay_ms = {}", out.value(delay));
+            }
+            if let Some(wait) = config.max_retry_wait_secs {
+                println!("max_retry_wait_secs = {}", out.value(wait));
+            }
+            if let Some(timeout) = config.timeout_secs {
+                println!("timeout_secs = {}", out.value(timeout));
+            }
+            if let Some(proxy) = &config.proxy {
+                println!(
+                    "proxy = {}",
+                    out.value(format!("\"{}\"", redact_password(proxy)))
+                );
+            }
+            if let Some(per_minute) = config.requests_per_minute {
+                println!("requests_per_minute = {}", out.value(per_minute));
+            }
+            if let Some(ttl) = config.cache_ttl_secs {
+                println!("cache_ttl_secs = {}", out.value(ttl));
+            }
+            if let Some(path) = &config.ca_cert_path {
+                println!("ca_cert_path = {}", out.value(format!("\"{}\"", path)));
+            }
+            if let Some(insecure) = config.insecure_skip_tls_verify {
+                println!("insecure_skip_tls_verify = {}", out.value(insecure));
+            }
+            if let Some(style) = &config.api_style {
+                println!("api_style = {}", out.value(format!("\"{}\"", style)));
+            }
+            if let Some(count) = config.count {
+                println!("count = {}", out.value(count));
+            }
+            if let Some(max_diff_size) = config.max_diff_size {
+                println!("max_diff_size = {}", out.value(max_diff_size));
+            }
+            if let Some(temperature) = config.temperature {
+                println!("temperature = {}", out.value(temperature));
+            }
+            if let Some(top_p) = config.top_p {
+                println!("top_p = {}", out.value(top_p));
+            }
+            if let Some(penalty) = config.frequency_penalty {
+                println!("frequency_penalty = {}", out.value(penalty));
+            }
+            if let Some(penalty) = config.presence_penalty {
+                println!("presence_penalty = {}", out.value(penalty));
+            }
+            if let Some(seed) = config.seed {
+                println!("seed = {}", out.value(seed));
+            }
+            if let Some(stop) = &config.stop {
+                println!("stop = {}", out.value(format!("{:?}", stop)));
+            }
+            if let Some(max_tokens) = config.max_tokens {
+                println!("max_tokens = {}", out.value(max_tokens));
+            }
+            if let Some(window) = config.context_window {
+                println!("context_window = {}", out.value(window));
+            }
+            if let Some(max_cost) = config.max_cost {
+                println!("max_cost = {}", out.value(max_cost));
+            }
+            for (model, settings) in config.models.iter().flatten() {
+                if let Some(tokens) = settings.context_tokens {
+                    println!("models.{}.context_tokens = {}", model, out.value(tokens));
+                }
+                if let Some(tokens) = settings.max_output_tokens {
+                    println!("models.{}.max_output_tokens = {}", model, out.value(tokens));
+                }
+                if let Some(price) = settings.input_price {
+                    println!("models.{}.input_price = {}", model, out.value(price));
+                }
+                if let Some(price) = settings.output_price {
+                    println!("models.{}.output_price = {}", model, out.value(price));
+                }
+            }
+            if let Some(reasoning_model) = config.reasoning_model {
+                println!("reasoning_model = {}", out.value(reasoning_model));
+            }
+            if let Some(repos) = &config.trusted_repos {
+                println!("trusted_repos = {}", out.value(format!("{:?}", repos)));
             }
             println!("---");
         }
     }
+    Ok(())
 }
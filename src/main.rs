@@ -1,20 +1,72 @@
-use ai_commit::cli::{Cli, SubCommand};
-use ai_commit::config::load_config;
-use ai_commit::{handle_config_command, run_generate_commit};
-use clap::{CommandFactory, Parser};
+use ai_commit::cache;
+use ai_commit::cli::{CacheCmd, Cli, GenerateArgs, LanguageCmd, StatsCmd, SubCommand};
+use ai_commit::config::{load_config, load_merged_config};
+use ai_commit::doctor::run_doctor;
+use ai_commit::error::AiCommitError;
+use ai_commit::hook::{install_hook, uninstall_hook};
+use ai_commit::language::KNOWN_LANGUAGES;
+use ai_commit::output;
+use ai_commit::stats;
+use ai_commit::{
+    handle_config_command, handle_interrupts, list_models, recover_broken_config,
+    run_generate_commit,
+};
 use clap_complete::{generate, Generator, Shell};
 use std::env;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
-    let config = load_config();
+    let cli = Cli::parse_args();
+    output::init(cli.no_color, cli.quiet);
+    handle_interrupts();
+    if cli.verbose > 0 {
+        let level = match cli.verbose {
+            1 => tracing::Level::DEBUG,
+            _ => tracing::Level::TRACE,
+        };
+        tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(std::io::stderr)
+                    .with_ansi(output::printer().colours_stderr())
+                    .without_time()
+                    .with_target(false),
+            )
+            .with(Targets::new().with_target("ai_commit", level))
+            .init();
+    }
+    let config = match load_config() {
+        Ok(config) => config,
+        // `config` subcommands are how a broken file gets fixed.
+        Err(e @ AiCommitError::ConfigParse(_))
+            if matches!(cli.command, Some(SubCommand::Config(_))) =>
+        {
+            recover_broken_config(e).unwrap_or_else(|e| exit_with_error(e))
+        }
+        Err(e @ AiCommitError::ConfigParse(_)) => {
+            eprintln!(
+                "{}",
+                output::printer().error(format!("Configuration error: {}", e))
+            );
+            eprintln!(
+                "Fix config.toml, or run an `ai_commit config` command in a terminal to back it up and start over."
+            );
+            std::process::exit(e.exit_code());
+        }
+        Err(e) => exit_with_error(e),
+    };
 
     if let Some(maybe_shell) = cli.gen_completion {
         let shell_str = match maybe_shell {
             Some(s) => s,
             None => detect_current_shell().unwrap_or_else(|| {
-                eprintln!("Error: Could not detect current shell");
+                eprintln!(
+                    "{}",
+                    output::printer().error("Error: Could not detect current shell")
+                );
                 eprintln!("Please specify shell explicitly: --gen-completion <SHELL>");
                 eprintln!("Supported shells: bash, zsh, fish, power-shell, elvish");
                 std::process::exit(1);
@@ -24,18 +76,109 @@ async fn main() {
         return;
     }
 
-    match cli.command {
+    let out = output::printer();
+    let result = match cli.command {
         Some(SubCommand::Config(config_args)) => {
-            handle_config_command(config_args.command, config);
+            handle_config_command(config_args.command, config).await
         }
+        Some(SubCommand::Doctor) => match load_merged_config(config) {
+            Ok(config) => std::process::exit(run_doctor(&config).await),
+            Err(e) => Err(e),
+        },
+        Some(SubCommand::InstallHook { force }) => {
+            install_hook(force).map(|path| out.info(format_args!("Installed {}", path.display())))
+        }
+        Some(SubCommand::UninstallHook) => {
+            uninstall_hook().map(|path| out.info(format_args!("Removed {}", path.display())))
+        }
+        Some(SubCommand::Language {
+            command: LanguageCmd::List,
+        }) => {
+            for (tag, name) in KNOWN_LANGUAGES {
+                println!("{:<8} {}", tag, name);
+            }
+            Ok(())
+        }
+        Some(SubCommand::Stats { command: None }) => stats::print_summary(&config),
+        Some(SubCommand::Stats {
+            command: Some(StatsCmd::Reset),
+        }) => stats::reset().map(|()| out.info("Usage statistics cleared.")),
+        Some(SubCommand::Cache {
+            command: CacheCmd::Clear,
+        }) => cache::clear().map(|()| out.info("Response cache cleared.")),
+        Some(SubCommand::Models {
+            ref filter,
+            ref url,
+            ref provider,
+        }) => match load_merged_config(config) {
+            Ok(config) => {
+                let args = GenerateArgs {
+                    url: url.clone(),
+                    provider: provider.clone(),
+                    ..GenerateArgs::default()
+                };
+                list_models(&args, config, filter.as_deref()).await
+            }
+            Err(e) => Err(e),
+        },
+        Some(SubCommand::Generate(args)) => match load_merged_config(config) {
+            Ok(config) => run_generate_commit(*args, config).await,
+            Err(e) => Err(e),
+        },
         None => {
-            run_generate_commit(cli, config).await;
+            output::warn(
+                "Hint: running ai_commit without a subcommand is deprecated; use `ai_commit generate`.",
+            );
+            match load_merged_config(config) {
+                Ok(config) => run_generate_commit(cli.generate, config).await,
+                Err(e) => Err(e),
+            }
+        }
+    };
+    if let Err(e) = result {
+        exit_with_error(e);
+    }
+}
+
+fn exit_with_error(err: AiCommitError) -> ! {
+    let out = output::printer();
+    match &err {
+        AiCommitError::Network(_) | AiCommitError::Stream(_) | AiCommitError::Timeout { .. } => {
+            eprintln!("{}", out.error(format!("Network error: {}", err)));
+            eprintln!("Check your connection and the configured URL.");
+        }
+        AiCommitError::ApiError { .. } => {
+            eprintln!(
+                "{}",
+                out.error(format!("Error generating commit message:\n{}", err))
+            );
+        }
+        AiCommitError::Serialization { .. } | AiCommitError::EmptyResponse => {
+            eprintln!("{}", out.error(format!("Unexpected API response: {}", err)));
+        }
+        AiCommitError::ConfigRead(_)
+        | AiCommitError::ConfigParse(_)
+        | AiCommitError::ConfigWrite(_)
+        | AiCommitError::ConfigSerialize(_) => {
+            eprintln!("{}", out.error(format!("Configuration error: {}", err)));
+        }
+        AiCommitError::Credentials(_)
+        | AiCommitError::Git { .. }
+        | AiCommitError::Editor(_)
+        | AiCommitError::DiffRead { .. }
+        | AiCommitError::ConfigLookup(_)
+        | AiCommitError::Config(_)
+        | AiCommitError::Stats(_)
+        | AiCommitError::Cache(_)
+        | AiCommitError::Interrupted => {
+            eprintln!("{}", out.error(&err));
         }
     }
+    std::process::exit(err.exit_code());
 }
 
 fn generate_completion_script(shell_str: &str) {
-    let mut cmd = Cli::command();
+    let mut cmd = Cli::app();
 
     let shell = match shell_str.to_lowercase().as_str() {
         "bash" => Shell::Bash,
@@ -44,7 +187,10 @@ fn generate_completion_script(shell_str: &str) {
         "power-shell" | "powershell" | "ps" | "pwsh" => Shell::PowerShell,
         "elvish" => Shell::Elvish,
         _ => {
-            eprintln!("Error: Unsupported shell '{}'", shell_str);
+            eprintln!(
+                "{}",
+                output::printer().error(format!("Error: Unsupported shell '{}'", shell_str))
+            );
             eprintln!("Supported shells: bash, zsh, fish, power-shell, elvish");
             std::process::exit(1);
         }
@@ -56,7 +202,10 @@ fn generate_completion_script(shell_str: &str) {
     generate(shell, &mut cmd, "ai_commit", &mut buf);
 
     std::fs::write(&file_name, buf).unwrap_or_else(|e| {
-        eprintln!("Error writing completion script: {}", e);
+        eprintln!(
+            "{}",
+            output::printer().error(format!("Error writing completion script: {}", e))
+        );
         std::process::exit(1);
     });
 
@@ -0,0 +1,170 @@
+// ===================================================================
+// Terminal Output
+// ===================================================================
+
+use std::env;
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+
+use owo_colors::OwoColorize;
+
+static PRINTER: OnceLock<Printer> = OnceLock::new();
+
+/// Colours text for stdout and stderr: errors in red, warnings in yellow,
+/// generated messages in green and config values in cyan. Each stream is
+/// decided separately, so piping stdout keeps colour on stderr.
+///
+/// With `--quiet`, status lines and warnings are dropped, leaving only the
+/// requested output (such as the message with `--dry-run`) and errors.
+#[derive(Debug, Clone, Copy)]
+pub struct Printer {
+    stdout: bool,
+    stderr: bool,
+    quiet: bool,
+}
+
+impl Printer {
+    /// Colour for each stream given explicitly, e.g. to check the styling
+    /// without a terminal.
+    pub fn new(stdout: bool, stderr: bool, quiet: bool) -> Printer {
+        Printer {
+            stdout,
+            stderr,
+            quiet,
+        }
+    }
+
+    /// Colour is off with `--no-color`, with `NO_COLOR` set to anything but
+    /// an empty string, and for streams that are not a terminal.
+    pub fn detect(no_color: bool, quiet: bool) -> Printer {
+        let enabled = !no_color && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+        Printer::new(
+            enabled && io::stdout().is_terminal(),
+            enabled && io::stderr().is_terminal(),
+            quiet,
+        )
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Whether stderr is coloured, for output written by other crates.
+    pub fn colours_stderr(&self) -> bool {
+        self.stderr
+    }
+
+    /// Prints a status line, such as a confirmation, on stdout.
+    pub fn info(&self, text: impl Display) {
+        let _ = self.info_to(&mut io::stdout().lock(), text);
+    }
+
+    /// Prints a warning or note on stderr.
+    pub fn warn(&self, text: impl Display) {
+        let _ = self.warn_to(&mut io::stderr().lock(), text);
+    }
+
+    /// [`info`](Self::info), written to `out` instead of stdout.
+    pub fn info_to(&self, out: &mut impl Write, text: impl Display) -> io::Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        writeln!(out, "{}", text)
+    }
+
+    /// [`warn`](Self::warn), written to `out` instead of stderr.
+    pub fn warn_to(&self, out: &mut impl Write, text: impl Display) -> io::Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        writeln!(out, "{}", self.warning(text))
+    }
+
+    /// An error, for stderr.
+    pub fn error(&self, text: impl Display) -> String {
+        style(self.stderr, text, |text| text.red().to_string())
+    }
+
+    /// A warning, for stderr.
+    pub fn warning(&self, text: impl Display) -> String {
+        style(self.stderr, text, |text| text.yellow().to_string())
+    }
+
+    /// A generated commit message, for stdout.
+    pub fn message(&self, text: impl Display) -> String {
+        style(self.stdout, text, |text| text.green().to_string())
+    }
+
+    /// A config value, for stdout.
+    pub fn value(&self, text: impl Display) -> String {
+        style(self.stdout, text, |text| text.cyan().to_string())
+    }
+}
+
+fn style(enabled: bool, text: impl Display, paint: impl Fn(&str) -> String) -> String {
+    let text = text.to_string();
+    if enabled { paint(&text) } else { text }
+}
+
+/// Sets up colour and quiet mode for this run. Called once from `main`;
+/// later calls keep the first setting.
+pub fn init(no_color: bool, quiet: bool) {
+    PRINTER.get_or_init(|| Printer::detect(no_color, quiet));
+}
+
+/// The printer set up by `init`, or one detected from the environment.
+pub fn printer() -> &'static Printer {
+    PRINTER.get_or_init(|| Printer::detect(false, false))
+}
+
+/// Prints a warning on stderr, unless `--quiet`.
+pub fn warn(text: impl Display) {
+    printer().warn(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn colour_wraps_text_in_ansi_codes() {
+        let printer = Printer::new(true, true, false);
+        assert_eq!(printer.message("feat: x"), "\u{1b}[32mfeat: x\u{1b}[39m");
+        assert_eq!(printer.error("boom"), "\u{1b}[31mboom\u{1b}[39m");
+        assert_eq!(
+            written(|out| printer.warn_to(out, "careful")),
+            "\u{1b}[33mcareful\u{1b}[39m\n"
+        );
+    }
+
+    #[test]
+    fn no_colour_leaves_text_plain() {
+        let printer = Printer::new(false, false, false);
+        assert_eq!(printer.message("feat: x"), "feat: x");
+        assert_eq!(printer.value(42), "42");
+        assert_eq!(written(|out| printer.warn_to(out, "careful")), "careful\n");
+        assert_eq!(written(|out| printer.info_to(out, "Saved.")), "Saved.\n");
+    }
+
+    #[test]
+    fn streams_are_coloured_separately() {
+        let printer = Printer::new(false, true, false);
+        assert_eq!(printer.message("feat: x"), "feat: x");
+        assert_ne!(printer.error("boom"), "boom");
+    }
+
+    #[test]
+    fn quiet_drops_status_lines_and_warnings() {
+        let printer = Printer::new(true, true, true);
+        assert_eq!(written(|out| printer.info_to(out, "Saved.")), "");
+        assert_eq!(written(|out| printer.warn_to(out, "careful")), "");
+        assert!(printer.error("boom").contains("boom"));
+    }
+}
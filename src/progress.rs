@@ -0,0 +1,75 @@
+// ===================================================================
+// Progress Spinner
+// ===================================================================
+
+use std::io::{self, IsTerminal};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::output;
+
+/// The spinner on screen, if any, so notes can be printed around it.
+static ACTIVE: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// A "Generating commit message…" spinner with the elapsed time, shown on
+/// stderr while waiting for the API. It is cleared by `stop` or when dropped,
+/// so an error never leaves it behind.
+pub struct Spinner;
+
+impl Spinner {
+    /// Starts the spinner, unless `hidden`, `--quiet` or stderr is not a
+    /// terminal.
+    pub fn start(hidden: bool) -> Spinner {
+        if !hidden && !output::printer().is_quiet() && io::stderr().is_terminal() {
+            let bar = ProgressBar::new_spinner()
+                .with_style(
+                    ProgressStyle::with_template("{spinner} {msg} ({elapsed})")
+                        .expect("valid spinner template"),
+                )
+                .with_message("Generating commit message…");
+            bar.enable_steady_tick(Duration::from_millis(100));
+            *ACTIVE.lock().unwrap() = Some(bar);
+        }
+        Spinner
+    }
+
+    /// Clears the spinner. Further calls do nothing.
+    pub fn stop(&self) {
+        clear();
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Clears whatever spinner is on screen, e.g. before exiting on Ctrl-C.
+pub fn clear() {
+    if let Some(bar) = ACTIVE.lock().unwrap().take() {
+        bar.finish_and_clear();
+    }
+}
+
+/// Prints `message` on stderr, hiding the spinner meanwhile so the two
+/// don't end up on the same line.
+pub fn note(message: &str) {
+    if output::printer().is_quiet() {
+        return;
+    }
+    match ACTIVE.lock().unwrap().as_ref() {
+        Some(bar) => bar.suspend(|| eprintln!("{}", message)),
+        None => eprintln!("{}", message),
+    }
+}
+
+/// [`output::warn`], hiding the spinner meanwhile like [`note`].
+pub fn warn(message: &str) {
+    match ACTIVE.lock().unwrap().as_ref() {
+        Some(bar) => bar.suspend(|| output::warn(message)),
+        None => output::warn(message),
+    }
+}
@@ -0,0 +1,225 @@
+// ===================================================================
+// Prompt Size Budget
+// ===================================================================
+
+use crate::ai_commit::{DiffFile, GenerationOptions, truncate_diff};
+
+/// Rough characters per token for code and English. Real tokenizers vary by
+/// model; this errs on the side of sending a little less.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Context window assumed for models not in `CONTEXT_WINDOWS`.
+pub const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+
+/// Tokens kept free for the reply when no `max_tokens` is configured.
+const REPLY_RESERVE: usize = 1_024;
+
+/// Context windows in tokens, matched by model-name prefix. More specific
+/// names come first.
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+    ("gemini", 1_048_576),
+    ("deepseek", 64_000),
+    ("mistral", 32_000),
+    ("llama-3.1", 128_000),
+    ("llama3.1", 128_000),
+    ("llama-3", 8_192),
+    ("llama3", 8_192),
+    ("qwen2.5", 32_768),
+];
+
+/// Estimated tokens in `text`.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// The context window of `model`, from the table or the default.
+pub fn context_window(model: &str) -> usize {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map_or(DEFAULT_CONTEXT_WINDOW, |(_, window)| *window)
+}
+
+/// Tokens left for the diff once the prompts and the reply are accounted for.
+pub fn diff_budget(opts: &GenerationOptions) -> usize {
+    let window = opts
+        .context_window
+        .unwrap_or_else(|| context_window(&opts.model));
+    let request = opts.chat_request("");
+    let prompts = estimate_tokens(&request.system) + estimate_tokens(&request.user);
+    let reply = opts
+        .max_tokens
+        .map_or(REPLY_RESERVE, |tokens| tokens as usize);
+    window.saturating_sub(prompts + reply)
+}
+
+/// A diff cut down to a token budget.
+pub struct Fitted {
+    pub diff: String,
+    /// Paths of the files left out.
+    pub omitted: Vec<String>,
+    /// Estimated tokens removed.
+    pub omitted_tokens: usize,
+}
+
+/// Fits `diff` into `budget` tokens by leaving out whole files, largest
+/// first, and noting which ones at the end. If that is not enough, the
+/// hunks of what remains are shortened as well.
+pub fn fit_diff(diff: &str, budget: usize) -> Fitted {
+    let tokens = estimate_tokens(diff);
+    if tokens <= budget {
+        return Fitted {
+            diff: diff.to_string(),
+            omitted: Vec::new(),
+            omitted_tokens: 0,
+        };
+    }
+
+    let files = DiffFile::split(diff);
+    let rendered: Vec<String> = files.iter().map(|file| file.render(usize::MAX)).collect();
+    let mut largest_first: Vec<usize> = (0..files.len())
+        .filter(|&i| files[i].path().is_some())
+        .collect();
+    largest_first.sort_by_key(|&i| std::cmp::Reverse(rendered[i].len()));
+
+    let note = |omitted: &[String]| {
+        format!(
+            "[…left out to fit the model's context window: {}…]\n",
+            omitted.join(", ")
+        )
+    };
+    let mut kept = vec![true; files.len()];
+    let mut omitted = Vec::new();
+    let mut size: usize = rendered.iter().map(|file| estimate_tokens(file)).sum();
+    for i in largest_first {
+        if size + estimate_tokens(&note(&omitted)) <= budget {
+            break;
+        }
+        kept[i] = false;
+        size -= estimate_tokens(&rendered[i]);
+        omitted.push(files[i].path().unwrap_or_default().to_string());
+    }
+
+    let mut fitted: String = rendered
+        .iter()
+        .zip(&kept)
+        .filter(|(_, kept)| **kept)
+        .map(|(file, _)| file.as_str())
+        .collect();
+    if !omitted.is_empty() {
+        fitted.push_str(&note(&omitted));
+    }
+    if estimate_tokens(&fitted) > budget {
+        fitted = truncate_diff(&fitted, budget * CHARS_PER_TOKEN);
+    }
+    Fitted {
+        omitted_tokens: tokens.saturating_sub(estimate_tokens(&fitted)),
+        diff: fitted,
+        omitted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, lines: usize) -> String {
+        let mut diff = format!(
+            "diff --git a/{name} b/{name}\n--- a/{name}\n+++ b/{name}\n@@ -0,0 +1,{lines} @@\n"
+        );
+        for i in 0..lines {
+            diff.push_str(&format!("+{} line {}\n", name, i));
+        }
+        diff
+    }
+
+    #[test]
+    fn tokens_are_estimated_from_characters() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        // Characters, not bytes.
+        assert_eq!(estimate_tokens("éééé"), 1);
+    }
+
+    #[test]
+    fn context_window_matches_prefixes_after_a_vendor() {
+        assert_eq!(context_window("gpt-4o-mini"), 128_000);
+        assert_eq!(context_window("openai/gpt-4o"), 128_000);
+        assert_eq!(context_window("gpt-4"), 8_192);
+        assert_eq!(context_window("some-local-model"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn diff_budget_leaves_room_for_prompts_and_reply() {
+        let opts = GenerationOptions {
+            model: "gpt-4".to_string(),
+            ..GenerationOptions::default()
+        };
+        let budget = diff_budget(&opts);
+        assert!(budget < DEFAULT_CONTEXT_WINDOW - REPLY_RESERVE);
+
+        let roomier = GenerationOptions {
+            max_tokens: Some(24),
+            ..opts.clone()
+        };
+        assert_eq!(diff_budget(&roomier), budget + REPLY_RESERVE - 24);
+
+        let tiny = GenerationOptions {
+            context_window: Some(10),
+            ..opts
+        };
+        assert_eq!(diff_budget(&tiny), 0);
+    }
+
+    #[test]
+    fn diffs_within_budget_are_untouched() {
+        let diff = file("a.rs", 5);
+        let fitted = fit_diff(&diff, estimate_tokens(&diff));
+        assert_eq!(fitted.diff, diff);
+        assert!(fitted.omitted.is_empty());
+        assert_eq!(fitted.omitted_tokens, 0);
+    }
+
+    #[test]
+    fn largest_files_are_left_out_and_named() {
+        let diff = [file("small.rs", 5), file("big.rs", 300), file("mid.rs", 40)].concat();
+        let budget = estimate_tokens(&diff) / 2;
+        let fitted = fit_diff(&diff, budget);
+
+        assert!(estimate_tokens(&fitted.diff) <= budget);
+        assert_eq!(fitted.omitted, vec!["big.rs"]);
+        assert!(fitted.diff.contains("+small.rs line 4\n"));
+        assert!(fitted.diff.contains("+mid.rs line 39\n"));
+        assert!(
+            fitted
+                .diff
+                .ends_with("[…left out to fit the model's context window: big.rs…]\n")
+        );
+        assert!(fitted.omitted_tokens > 0);
+    }
+
+    #[test]
+    fn the_rest_is_cut_when_leaving_out_files_is_not_enough() {
+        // A `git show` header is not a file and can't be left out.
+        let header: String = (0..200)
+            .map(|i| format!("    message line {}\n", i))
+            .collect();
+        let diff = format!("commit 0123abcd\n{}{}", header, file("a.rs", 50));
+        let fitted = fit_diff(&diff, 60);
+
+        assert!(estimate_tokens(&fitted.diff) <= 60);
+        assert!(fitted.diff.contains("commit 0123abcd\n"));
+        assert!(fitted.diff.ends_with("[…truncated…]"));
+    }
+}
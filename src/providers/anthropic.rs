@@ -0,0 +1,216 @@
+// ===================================================================
+// Anthropic Messages API
+// ===================================================================
+
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ApiProvider, ChatRequest, ChatResponse, Message, first_message, parse_json, to_json,
+    with_api_path,
+};
+use crate::ai_commit::{GenerationOptions, TokenUsage};
+use crate::error::AiCommitError;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+pub(crate) const ANTHROPIC_MAX_TOKENS: u32 = 1024;
+
+#[derive(Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    system: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+/// The sampling settings the Messages API takes, here and on Bedrock.
+pub(crate) const ANTHROPIC_SAMPLING_SETTINGS: &[&str] = &["temperature", "top_p"];
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct AnthropicResponse {
+    content: Vec<AnthropicContent>,
+    #[serde(default)]
+    usage: Option<AnthropicUsage>,
+    /// `max_tokens` when the reply hit the limit.
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicContent {
+    #[serde(default)]
+    text: String,
+}
+
+impl AnthropicResponse {
+    pub fn into_chat_response(self) -> Result<ChatResponse, AiCommitError> {
+        let mut response = first_message(self.content.into_iter().next().map(|c| c.text))?;
+        response.usage = self.usage.map(|usage| TokenUsage {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: usage.input_tokens + usage.output_tokens,
+        });
+        response.cut_off = self.stop_reason.as_deref() == Some("max_tokens");
+        Ok(response)
+    }
+}
+
+pub struct AnthropicProvider {
+    url: String,
+    api_key: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(opts: &GenerationOptions) -> AnthropicProvider {
+        // Both `https://api.anthropic.com` and `.../v1` are accepted as bases.
+        let path = if opts.url.trim_end_matches('/').ends_with("/v1") {
+            "/messages"
+        } else {
+            "/v1/messages"
+        };
+        AnthropicProvider {
+            url: with_api_path(&opts.url, path, &["/messages"]),
+            api_key: opts.api_key.clone(),
+        }
+    }
+}
+
+impl ApiProvider for AnthropicProvider {
+    fn endpoint(&self, _req: &ChatRequest) -> String {
+        self.url.clone()
+    }
+
+    fn models_endpoint(&self) -> Option<String> {
+        let base = self.url.trim_end_matches('/').strip_suffix("/messages")?;
+        Some(format!("{}/models", base))
+    }
+
+    fn sampling_settings(&self) -> &'static [&'static str] {
+        ANTHROPIC_SAMPLING_SETTINGS
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        to_json(&AnthropicRequest {
+            model: req.model.clone(),
+            max_tokens: req.max_tokens.unwrap_or(ANTHROPIC_MAX_TOKENS),
+            system: req.system.clone(),
+            messages: vec![Message::new("user", &req.user)],
+            temperature: req.temperature,
+            top_p: req.top_p,
+            stop_sequences: req.stop.clone(),
+        })
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        Ok(request
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION))
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        parse_json::<AnthropicResponse>(body)?.into_chat_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider() -> AnthropicProvider {
+        AnthropicProvider::new(&GenerationOptions {
+            url: "https://api.anthropic.com".to_string(),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn request_puts_the_system_prompt_at_the_top_level() {
+        let req = GenerationOptions {
+            model: "claude-3-5-haiku-latest".to_string(),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let body = provider().body(&req);
+
+        assert_eq!(body["model"], "claude-3-5-haiku-latest");
+        assert_eq!(body["system"], json!(req.system));
+        assert_eq!(body["max_tokens"], ANTHROPIC_MAX_TOKENS);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert!(body.get("stop_sequences").is_none());
+    }
+
+    #[test]
+    fn response_gives_text_and_usage() {
+        let response = provider()
+            .parse(
+                &json!({
+                    "content": [{ "type": "text", "text": "fix: close the file" }],
+                    "usage": { "input_tokens": 20, "output_tokens": 5 },
+                    "stop_reason": "max_tokens"
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(response.content, "fix: close the file");
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 20);
+        assert_eq!(usage.total_tokens, 25);
+        assert!(response.cut_off);
+    }
+
+    #[test]
+    fn endpoint_accepts_bases_with_and_without_v1() {
+        let req = GenerationOptions::default().chat_request("");
+        assert_eq!(
+            provider().endpoint(&req),
+            "https://api.anthropic.com/v1/messages"
+        );
+        let with_v1 = AnthropicProvider::new(&GenerationOptions {
+            url: "https://api.anthropic.com/v1/".to_string(),
+            ..GenerationOptions::default()
+        });
+        assert_eq!(
+            with_v1.endpoint(&req),
+            "https://api.anthropic.com/v1/messages"
+        );
+    }
+
+    #[test]
+    fn temperature_and_top_p_are_sent_only_when_set() {
+        let unset = GenerationOptions::default().chat_request("");
+        assert!(provider().body(&unset).get("temperature").is_none());
+
+        let req = GenerationOptions {
+            temperature: Some(0.5),
+            top_p: Some(0.25),
+            seed: Some(7),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let body = provider().body(&req);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["top_p"], 0.25);
+        assert!(body.get("seed").is_none());
+        assert_eq!(provider().sampling_settings(), ["temperature", "top_p"]);
+    }
+}
@@ -0,0 +1,149 @@
+// ===================================================================
+// Azure OpenAI
+// ===================================================================
+
+use reqwest::{RequestBuilder, Url};
+
+use super::openai::{OpenAiRequest, OpenAiResponse, OpenAiStreamChunk};
+use super::{ApiProvider, ChatRequest, ChatResponse, StreamEvent, parse_json, to_json};
+use crate::ai_commit::GenerationOptions;
+use crate::error::AiCommitError;
+
+const AZURE_DEFAULT_API_VERSION: &str = "2024-02-01";
+
+/// Azure speaks the OpenAI wire format but authenticates with an `api-key`
+/// header and routes by deployment.
+pub struct AzureOpenAiProvider {
+    url: String,
+    api_key: String,
+    /// Defaults to the model name.
+    deployment: Option<String>,
+    api_version: String,
+}
+
+impl AzureOpenAiProvider {
+    pub fn new(opts: &GenerationOptions) -> AzureOpenAiProvider {
+        AzureOpenAiProvider {
+            url: opts.url.clone(),
+            api_key: opts.api_key.clone(),
+            deployment: opts.azure_deployment.clone(),
+            api_version: opts
+                .azure_api_version
+                .clone()
+                .unwrap_or_else(|| AZURE_DEFAULT_API_VERSION.to_string()),
+        }
+    }
+}
+
+impl ApiProvider for AzureOpenAiProvider {
+    /// Builds `{base}/openai/deployments/{deployment}/chat/completions?api-version=..`
+    /// from an Azure resource endpoint. URLs that already name a deployment are
+    /// used as given, with the `api-version` added if missing.
+    fn endpoint(&self, req: &ChatRequest) -> String {
+        if self.url.contains("/openai/deployments/") {
+            if self.url.contains("api-version=") {
+                self.url.clone()
+            } else {
+                let separator = if self.url.contains('?') { '&' } else { '?' };
+                format!("{}{}api-version={}", self.url, separator, self.api_version)
+            }
+        } else {
+            format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                self.url.trim_end_matches('/'),
+                self.deployment.as_deref().unwrap_or(&req.model),
+                self.api_version
+            )
+        }
+    }
+
+    fn honours_model(&self) -> bool {
+        self.deployment.is_none() && !self.url.contains("/openai/deployments/")
+    }
+
+    /// The deployment in the URL selects the model, so `model` is left out.
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        let mut body = to_json(&OpenAiRequest::new(req));
+        if let Some(fields) = body.as_object_mut() {
+            fields.remove("model");
+        }
+        body
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        Ok(request.header("api-key", &self.api_key))
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        parse_json::<OpenAiResponse>(body)?.into_chat_response()
+    }
+
+    fn supports_multiple_choices(&self) -> bool {
+        true
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn parse_stream_event(&self, data: &str) -> Result<StreamEvent, AiCommitError> {
+        OpenAiStreamChunk::parse_event(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider() -> AzureOpenAiProvider {
+        AzureOpenAiProvider::new(&GenerationOptions {
+            url: "https://example.openai.azure.com".to_string(),
+            model: "gpt-4o".to_string(),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn request_leaves_the_model_to_the_deployment() {
+        let req = GenerationOptions {
+            model: "gpt-4o".to_string(),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let body = provider().body(&req);
+
+        assert!(body.get("model").is_none());
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], json!(req.user));
+        assert_eq!(
+            provider().endpoint(&req),
+            "https://example.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-02-01"
+        );
+    }
+
+    #[test]
+    fn response_is_read_in_the_openai_shape() {
+        let response = provider()
+            .parse(
+                &json!({
+                    "choices": [{
+                        "message": { "role": "assistant", "content": "fix: close the file" },
+                        "finish_reason": "length"
+                    }],
+                    "usage": { "prompt_tokens": 20, "completion_tokens": 5, "total_tokens": 25 }
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(response.content, "fix: close the file");
+        assert_eq!(response.usage.unwrap().total_tokens, 25);
+        assert!(response.cut_off);
+    }
+}
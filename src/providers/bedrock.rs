@@ -0,0 +1,375 @@
+// ===================================================================
+// AWS Bedrock
+// ===================================================================
+
+use std::{env, fs};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use directories::BaseDirs;
+use hmac::{Hmac, Mac};
+use reqwest::{RequestBuilder, Url};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::anthropic::{ANTHROPIC_MAX_TOKENS, ANTHROPIC_SAMPLING_SETTINGS, AnthropicResponse};
+use super::{ApiProvider, ChatRequest, ChatResponse, Message, execute, parse_json, to_json};
+use crate::ai_commit::GenerationOptions;
+use crate::error::AiCommitError;
+
+const BEDROCK_ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+
+#[derive(Serialize)]
+struct BedrockAnthropicRequest {
+    anthropic_version: &'static str,
+    max_tokens: u32,
+    system: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+/// Invokes Anthropic models through AWS Bedrock, signing each request with
+/// SigV4 credentials from the environment or `~/.aws/credentials`.
+pub struct BedrockProvider {
+    url: String,
+    region: Option<String>,
+}
+
+impl BedrockProvider {
+    pub fn new(opts: &GenerationOptions) -> BedrockProvider {
+        BedrockProvider {
+            url: opts.url.clone(),
+            region: opts.region.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ApiProvider for BedrockProvider {
+    fn endpoint(&self, req: &ChatRequest) -> String {
+        if self.url.contains("/invoke") {
+            self.url.clone()
+        } else {
+            format!(
+                "{}/model/{}/invoke",
+                self.url.trim_end_matches('/'),
+                aws_uri_encode(&req.model)
+            )
+        }
+    }
+
+    fn honours_model(&self) -> bool {
+        !self.url.contains("/invoke")
+    }
+
+    fn sampling_settings(&self) -> &'static [&'static str] {
+        ANTHROPIC_SAMPLING_SETTINGS
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        to_json(&BedrockAnthropicRequest {
+            anthropic_version: BEDROCK_ANTHROPIC_VERSION,
+            max_tokens: req.max_tokens.unwrap_or(ANTHROPIC_MAX_TOKENS),
+            system: req.system.clone(),
+            messages: vec![Message::new("user", &req.user)],
+            temperature: req.temperature,
+            top_p: req.top_p,
+            stop_sequences: req.stop.clone(),
+        })
+    }
+
+    fn authorize(
+        &self,
+        mut request: RequestBuilder,
+        url: &Url,
+        body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        let region = self.region.as_deref().ok_or_else(|| {
+            AiCommitError::Config(
+                "AWS region not set. Please run `ai_commit config set-region <REGION>` or set AWS_REGION"
+                    .to_string(),
+            )
+        })?;
+        let credentials = AwsCredentials::load()?;
+        for (name, value) in sigv4_headers(url, body, &credentials, region, "bedrock", Utc::now()) {
+            request = request.header(name, value);
+        }
+        Ok(request)
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        parse_json::<AnthropicResponse>(body)?.into_chat_response()
+    }
+
+    async fn complete(&self, req: &ChatRequest) -> Result<ChatResponse, AiCommitError> {
+        match execute(self, req).await {
+            Err(AiCommitError::ApiError {
+                status: 403, body, ..
+            }) if body.to_lowercase().contains("expired") => Err(AiCommitError::Credentials(
+                "AWS credentials have expired. Refresh them (e.g. `aws sso login`) and try again."
+                    .to_string(),
+            )),
+            other => other,
+        }
+    }
+}
+
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// Reads credentials from the standard AWS environment variables, falling
+    /// back to the shared credentials file for `$AWS_PROFILE` (or `default`).
+    fn load() -> Result<AwsCredentials, AiCommitError> {
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(AwsCredentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+
+        let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+        let path = env::var("AWS_SHARED_CREDENTIALS_FILE")
+            .ok()
+            .map(Into::into)
+            .or_else(|| BaseDirs::new().map(|dirs| dirs.home_dir().join(".aws/credentials")));
+        let missing = || {
+            AiCommitError::Credentials(format!(
+                "AWS credentials not found. Set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, \
+                or add a [{}] profile to ~/.aws/credentials.",
+                profile
+            ))
+        };
+        let contents = path
+            .and_then(|path| fs::read_to_string(path).ok())
+            .ok_or_else(missing)?;
+        AwsCredentials::from_profile(&contents, &profile).ok_or_else(missing)
+    }
+
+    /// Reads the `[profile]` section of a shared credentials file.
+    fn from_profile(contents: &str, profile: &str) -> Option<AwsCredentials> {
+        let mut in_profile = false;
+        let (mut access_key_id, mut secret_access_key, mut session_token) = (None, None, None);
+        for line in contents.lines().map(str::trim) {
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                in_profile = section.trim() == profile;
+            } else if let (true, Some((key, value))) = (in_profile, line.split_once('=')) {
+                let value = Some(value.trim().to_string());
+                match key.trim() {
+                    "aws_access_key_id" => access_key_id = value,
+                    "aws_secret_access_key" => secret_access_key = value,
+                    "aws_session_token" => session_token = value,
+                    _ => {}
+                }
+            }
+        }
+
+        Some(AwsCredentials {
+            access_key_id: access_key_id?,
+            secret_access_key: secret_access_key?,
+            session_token,
+        })
+    }
+}
+
+/// Percent-encodes everything except RFC 3986 unreserved characters, as
+/// SigV4 requires.
+fn aws_uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Computes the AWS Signature Version 4 headers for a POST request made at
+/// `now`.
+fn sigv4_headers(
+    url: &Url,
+    body: &[u8],
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    now: DateTime<Utc>,
+) -> Vec<(&'static str, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    // Non-S3 services expect each path segment to be encoded a second time.
+    let canonical_uri = url
+        .path()
+        .split('/')
+        .map(aws_uri_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut query: Vec<_> = url
+        .query_pairs()
+        .map(|(k, v)| format!("{}={}", aws_uri_encode(&k), aws_uri_encode(&v)))
+        .collect();
+    query.sort();
+
+    let mut headers = vec![("host", host), ("x-amz-date", amz_date.clone())];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "POST\n{}\n{}\n{}\n{}\n{}",
+        canonical_uri,
+        query.join("&"),
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let date_key = hmac_sha256(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        &date,
+    );
+    let region_key = hmac_sha256(&date_key, region);
+    let service_key = hmac_sha256(&region_key, service);
+    let signing_key = hmac_sha256(&service_key, "aws4_request");
+    let signature = hex::encode(hmac_sha256(&signing_key, &string_to_sign));
+
+    headers.retain(|(name, _)| *name != "host");
+    headers.push((
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ));
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn example_credentials(session_token: Option<&str>) -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: session_token.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn signature_matches_the_aws_post_vanilla_test_vector() {
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = sigv4_headers(
+            &url,
+            b"",
+            &example_credentials(None),
+            "us-east-1",
+            "service",
+            now,
+        );
+
+        assert_eq!(
+            headers,
+            [
+                ("x-amz-date", "20150830T123600Z".to_string()),
+                (
+                    "authorization",
+                    "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                     SignedHeaders=host;x-amz-date, \
+                     Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn session_token_is_sent_and_signed() {
+        let url = Url::parse("https://bedrock-runtime.us-east-1.amazonaws.com/model/a%3Ab/invoke")
+            .unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let headers = sigv4_headers(
+            &url,
+            b"{}",
+            &example_credentials(Some("token")),
+            "us-east-1",
+            "bedrock",
+            now,
+        );
+
+        assert!(headers.contains(&("x-amz-security-token", "token".to_string())));
+        let authorization = &headers.last().unwrap().1;
+        assert!(authorization.contains("SignedHeaders=host;x-amz-date;x-amz-security-token,"));
+    }
+
+    #[test]
+    fn credentials_are_read_from_the_named_profile() {
+        let contents = "[default]\naws_access_key_id = DEFAULT\naws_secret_access_key = x\n\n\
+                        [work]\naws_access_key_id = AKIDWORK\naws_secret_access_key = secret\n\
+                        aws_session_token = token\n";
+
+        let work = AwsCredentials::from_profile(contents, "work").unwrap();
+        assert_eq!(work.access_key_id, "AKIDWORK");
+        assert_eq!(work.secret_access_key, "secret");
+        assert_eq!(work.session_token.as_deref(), Some("token"));
+
+        let default = AwsCredentials::from_profile(contents, "default").unwrap();
+        assert_eq!(default.access_key_id, "DEFAULT");
+        assert!(default.session_token.is_none());
+        assert!(AwsCredentials::from_profile(contents, "missing").is_none());
+    }
+
+    #[test]
+    fn temperature_and_top_p_are_sent_in_the_body() {
+        let opts = GenerationOptions {
+            temperature: Some(0.5),
+            top_p: Some(0.25),
+            ..GenerationOptions::default()
+        };
+        let body = BedrockProvider::new(&opts).body(&opts.chat_request(""));
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["top_p"], 0.25);
+    }
+}
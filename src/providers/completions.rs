@@ -0,0 +1,225 @@
+// ===================================================================
+// OpenAI Legacy Text Completions
+// ===================================================================
+
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+
+use super::openai::{OpenAiResponse, StreamOptions, models_url};
+use super::{
+    ApiProvider, ChatRequest, ChatResponse, StreamEvent, first_message, parse_json, to_json,
+    with_api_path,
+};
+use crate::ai_commit::{GenerationOptions, TokenUsage};
+use crate::error::AiCommitError;
+
+/// Upper bound on the reply; the completions API defaults to just 16 tokens.
+const COMPLETIONS_MAX_TOKENS: u32 = 1024;
+
+#[derive(Serialize)]
+struct CompletionsRequest {
+    model: String,
+    prompt: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct CompletionsResponse {
+    choices: Vec<TextChoice>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TextChoice {
+    text: String,
+    /// `length` when the reply hit `max_tokens`.
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+impl CompletionsResponse {
+    /// Checks whether `body` is a text-completion reply, so the chat backend
+    /// can suggest switching styles.
+    pub fn matches(body: &str) -> bool {
+        serde_json::from_str::<CompletionsResponse>(body).is_ok()
+    }
+}
+
+/// Flattens the system prompt and diff into the single prompt string the
+/// completions API takes.
+fn flatten_prompt(req: &ChatRequest) -> String {
+    format!("{}\n\n{}\n\nCommit message:\n", req.system, req.user)
+}
+
+/// Backend for servers that only expose `/v1/completions`, such as older
+/// text-generation-webui or vLLM setups.
+pub struct CompletionsProvider {
+    url: String,
+    api_key: String,
+}
+
+impl CompletionsProvider {
+    pub fn new(opts: &GenerationOptions) -> CompletionsProvider {
+        CompletionsProvider {
+            url: with_api_path(&opts.url, "/completions", &["/completions"]),
+            api_key: opts.api_key.clone(),
+        }
+    }
+}
+
+impl ApiProvider for CompletionsProvider {
+    fn endpoint(&self, _req: &ChatRequest) -> String {
+        self.url.clone()
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        to_json(&CompletionsRequest {
+            model: req.model.clone(),
+            prompt: flatten_prompt(req),
+            max_tokens: req.max_tokens.unwrap_or(COMPLETIONS_MAX_TOKENS),
+            temperature: req.temperature,
+            top_p: req.top_p,
+            frequency_penalty: req.frequency_penalty,
+            presence_penalty: req.presence_penalty,
+            seed: req.seed,
+            stop: req.stop.clone(),
+            stream: req.stream,
+            stream_options: StreamOptions::for_stream(req.stream),
+        })
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        Ok(request.bearer_auth(&self.api_key))
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        if serde_json::from_str::<OpenAiResponse>(body).is_ok() {
+            return Err(AiCommitError::Config(
+                "The endpoint returned a chat completion, not a text completion. \
+                Run `ai_commit config set-api-style chat` or point the URL at /v1/completions."
+                    .to_string(),
+            ));
+        }
+        let response = parse_json::<CompletionsResponse>(body)?;
+        let choice = response.choices.into_iter().next();
+        let cut_off = choice
+            .as_ref()
+            .is_some_and(|choice| choice.finish_reason.as_deref() == Some("length"));
+        let mut reply = first_message(choice.map(|choice| choice.text.trim().to_string()))?;
+        reply.usage = response.usage;
+        reply.cut_off = cut_off;
+        Ok(reply)
+    }
+
+    fn models_endpoint(&self) -> Option<String> {
+        models_url(&self.url)
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn parse_stream_event(&self, data: &str) -> Result<StreamEvent, AiCommitError> {
+        let chunk = parse_json::<CompletionsResponse>(data)?;
+        let choice = chunk.choices.into_iter().next();
+        Ok(StreamEvent {
+            cut_off: choice
+                .as_ref()
+                .is_some_and(|choice| choice.finish_reason.as_deref() == Some("length")),
+            delta: choice.map(|choice| choice.text),
+            usage: chunk.usage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider() -> CompletionsProvider {
+        CompletionsProvider::new(&GenerationOptions {
+            url: "https://api.openai.com/v1".to_string(),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn request_flattens_both_messages_into_one_prompt() {
+        let req = GenerationOptions {
+            model: "gpt-3.5-turbo-instruct".to_string(),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let body = provider().body(&req);
+
+        assert_eq!(body["model"], "gpt-3.5-turbo-instruct");
+        assert_eq!(body["max_tokens"], COMPLETIONS_MAX_TOKENS);
+        let prompt = body["prompt"].as_str().unwrap();
+        assert!(prompt.starts_with(&req.system));
+        assert!(prompt.contains(&req.user));
+        assert!(prompt.ends_with("Commit message:\n"));
+        assert!(body.get("messages").is_none() && body.get("stream").is_none());
+        assert_eq!(
+            provider().endpoint(&req),
+            "https://api.openai.com/v1/completions"
+        );
+    }
+
+    #[test]
+    fn response_gives_trimmed_text_and_usage() {
+        let response = provider()
+            .parse(
+                &json!({
+                    "choices": [{ "text": "\n fix: close the file\n", "finish_reason": "length" }],
+                    "usage": { "prompt_tokens": 20, "completion_tokens": 5, "total_tokens": 25 }
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(response.content, "fix: close the file");
+        assert_eq!(response.usage.unwrap().prompt_tokens, 20);
+        assert!(response.cut_off);
+    }
+
+    #[test]
+    fn chat_completion_reply_suggests_the_chat_style() {
+        let body = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "fix: x" } }]
+        });
+        let err = provider().parse(&body.to_string()).unwrap_err();
+        assert!(err.to_string().contains("set-api-style chat"));
+    }
+
+    #[test]
+    fn stream_chunks_carry_text_deltas() {
+        let event = provider()
+            .parse_stream_event(&json!({ "choices": [{ "text": "fix" }] }).to_string())
+            .unwrap();
+        assert_eq!(event.delta.as_deref(), Some("fix"));
+        assert!(!event.cut_off);
+    }
+}
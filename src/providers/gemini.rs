@@ -0,0 +1,231 @@
+// ===================================================================
+// Google Gemini generateContent
+// ===================================================================
+
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+
+use super::{ApiProvider, ChatRequest, ChatResponse, first_message, parse_json, to_json};
+use crate::ai_commit::GenerationOptions;
+use crate::error::AiCommitError;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    system_instruction: GeminiContent,
+    contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Serialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+impl GenerationConfig {
+    /// The reply limit and sampling settings, or `None` if none are set.
+    fn from_request(req: &ChatRequest) -> Option<GenerationConfig> {
+        Some(GenerationConfig {
+            max_output_tokens: req.max_tokens,
+            temperature: req.temperature,
+            top_p: req.top_p,
+            frequency_penalty: req.frequency_penalty,
+            presence_penalty: req.presence_penalty,
+            seed: req.seed,
+        })
+        .filter(|config| *config != GenerationConfig::default())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GeminiContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GeminiPart {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+}
+
+/// Model names come back as `models/gemini-1.5-flash`.
+#[derive(Deserialize, Debug)]
+struct GeminiModels {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiModel {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiCandidate {
+    content: GeminiContent,
+    /// `MAX_TOKENS` when the reply hit `maxOutputTokens`.
+    #[serde(default, rename = "finishReason")]
+    finish_reason: Option<String>,
+}
+
+/// Gemini takes the API key in an `x-goog-api-key` header rather than
+/// `Authorization`.
+pub struct GeminiProvider {
+    url: String,
+    api_key: String,
+}
+
+impl GeminiProvider {
+    pub fn new(opts: &GenerationOptions) -> GeminiProvider {
+        GeminiProvider {
+            url: opts.url.clone(),
+            api_key: opts.api_key.clone(),
+        }
+    }
+}
+
+impl ApiProvider for GeminiProvider {
+    /// Builds the `models/{model}:generateContent` endpoint from a Gemini base
+    /// URL, leaving fully-qualified endpoints untouched.
+    fn endpoint(&self, req: &ChatRequest) -> String {
+        if self.url.contains(":generateContent") {
+            self.url.clone()
+        } else {
+            format!(
+                "{}/models/{}:generateContent",
+                self.url.trim_end_matches('/'),
+                req.model
+            )
+        }
+    }
+
+    fn honours_model(&self) -> bool {
+        !self.url.contains(":generateContent")
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        to_json(&GeminiRequest {
+            system_instruction: GeminiContent {
+                role: None,
+                parts: vec![GeminiPart {
+                    text: req.system.clone(),
+                }],
+            },
+            contents: vec![GeminiContent {
+                role: Some("user".to_string()),
+                parts: vec![GeminiPart {
+                    text: req.user.clone(),
+                }],
+            }],
+            generation_config: GenerationConfig::from_request(req),
+        })
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        Ok(request.header("x-goog-api-key", &self.api_key))
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        let response = parse_json::<GeminiResponse>(body)?;
+        let candidate = response.candidates.into_iter().next();
+        let cut_off = candidate
+            .as_ref()
+            .is_some_and(|candidate| candidate.finish_reason.as_deref() == Some("MAX_TOKENS"));
+        let mut reply = first_message(
+            candidate
+                .and_then(|candidate| candidate.content.parts.into_iter().next())
+                .map(|part| part.text),
+        )?;
+        reply.cut_off = cut_off;
+        Ok(reply)
+    }
+
+    fn models_endpoint(&self) -> Option<String> {
+        let base = match self.url.find("/models/") {
+            Some(index) => &self.url[..index],
+            None => self.url.trim_end_matches('/'),
+        };
+        Some(format!("{}/models", base))
+    }
+
+    fn parse_models(&self, body: &str) -> Result<Vec<String>, AiCommitError> {
+        Ok(parse_json::<GeminiModels>(body)?
+            .models
+            .into_iter()
+            .map(|model| {
+                model
+                    .name
+                    .strip_prefix("models/")
+                    .map(str::to_string)
+                    .unwrap_or(model.name)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> GeminiProvider {
+        GeminiProvider::new(&GenerationOptions {
+            url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn reply_limit_is_sent_only_when_set() {
+        let mut req = GenerationOptions::default().chat_request("diff --git a/x b/x\n");
+        assert!(provider().body(&req).get("generationConfig").is_none());
+
+        req.max_tokens = Some(100);
+        assert_eq!(
+            provider().body(&req)["generationConfig"]["maxOutputTokens"],
+            100
+        );
+    }
+
+    #[test]
+    fn sampling_settings_go_in_the_generation_config() {
+        let req = GenerationOptions {
+            temperature: Some(0.5),
+            top_p: Some(0.25),
+            seed: Some(7),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let config = &provider().body(&req)["generationConfig"];
+        assert_eq!(config["temperature"], 0.5);
+        assert_eq!(config["topP"], 0.25);
+        assert_eq!(config["seed"], 7);
+        assert!(config.get("maxOutputTokens").is_none());
+    }
+}
@@ -0,0 +1,233 @@
+// ===================================================================
+// llama.cpp Server Native Completion
+// ===================================================================
+
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ApiProvider, ChatRequest, ChatResponse, StreamEvent, first_message, parse_json, to_json,
+};
+use crate::ai_commit::GenerationOptions;
+use crate::error::AiCommitError;
+use crate::template;
+
+/// ChatML, which most instruction-tuned GGUF models understand.
+pub const DEFAULT_CHAT_TEMPLATE: &str = "<|im_start|>system\n{system}<|im_end|>\n\
+<|im_start|>user\n{user}<|im_end|>\n<|im_start|>assistant\n";
+
+/// Upper bound on generated tokens; the server default is unlimited.
+const LLAMACPP_N_PREDICT: u32 = 512;
+
+#[derive(Serialize)]
+struct LlamaCppRequest {
+    prompt: String,
+    n_predict: u32,
+    /// Stops at the end-of-turn marker of the default template, and at any
+    /// configured stop sequences.
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+/// Both the full response and each streamed chunk carry `content`.
+#[derive(Deserialize, Debug)]
+struct LlamaCppResponse {
+    content: String,
+    /// Set when the reply hit `n_predict`.
+    #[serde(default)]
+    stopped_limit: bool,
+}
+
+/// llama.cpp's built-in server. It takes a raw prompt, so the system and user
+/// messages are rendered through a chat template with `{system}` and
+/// `{user}` placeholders.
+pub struct LlamaCppProvider {
+    url: String,
+    api_key: String,
+    template: String,
+}
+
+impl LlamaCppProvider {
+    pub fn new(opts: &GenerationOptions) -> LlamaCppProvider {
+        LlamaCppProvider {
+            url: opts.url.clone(),
+            api_key: opts.api_key.clone(),
+            template: opts
+                .chat_template
+                .clone()
+                .unwrap_or_else(|| DEFAULT_CHAT_TEMPLATE.to_string()),
+        }
+    }
+
+    /// Fills the template in one pass, so braces in the prompts themselves
+    /// are left as they are.
+    fn render(&self, req: &ChatRequest) -> String {
+        template::render(
+            &self.template,
+            &[("system", &req.system), ("user", &req.user)],
+        )
+    }
+}
+
+impl ApiProvider for LlamaCppProvider {
+    fn endpoint(&self, _req: &ChatRequest) -> String {
+        self.url.clone()
+    }
+
+    /// The server runs whichever model it was started with.
+    fn honours_model(&self) -> bool {
+        false
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        to_json(&LlamaCppRequest {
+            prompt: self.render(req),
+            n_predict: req.max_tokens.unwrap_or(LLAMACPP_N_PREDICT),
+            stop: std::iter::once("<|im_end|>".to_string())
+                .chain(req.stop.iter().cloned())
+                .collect(),
+            temperature: req.temperature,
+            top_p: req.top_p,
+            frequency_penalty: req.frequency_penalty,
+            presence_penalty: req.presence_penalty,
+            seed: req.seed,
+            stream: req.stream,
+        })
+    }
+
+    /// The server only checks a key when started with `--api-key`.
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        if self.api_key.is_empty() {
+            Ok(request)
+        } else {
+            Ok(request.bearer_auth(&self.api_key))
+        }
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        let response = parse_json::<LlamaCppResponse>(body)?;
+        let mut reply = first_message(
+            Some(response.content.trim().to_string()).filter(|content| !content.is_empty()),
+        )?;
+        reply.cut_off = response.stopped_limit;
+        Ok(reply)
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn parse_stream_event(&self, data: &str) -> Result<StreamEvent, AiCommitError> {
+        let chunk = parse_json::<LlamaCppResponse>(data)?;
+        Ok(StreamEvent {
+            delta: Some(chunk.content).filter(|content| !content.is_empty()),
+            usage: None,
+            cut_off: chunk.stopped_limit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider(chat_template: Option<&str>) -> LlamaCppProvider {
+        LlamaCppProvider::new(&GenerationOptions {
+            url: "http://localhost:8080/completion".to_string(),
+            chat_template: chat_template.map(str::to_string),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn request_renders_the_chat_template_and_stops_at_end_of_turn() {
+        let mut req = GenerationOptions::default().chat_request("diff --git a/x b/x\n");
+        req.system = "SYS".to_string();
+        req.user = "USER".to_string();
+        req.stop = vec!["\n\n".to_string()];
+
+        let body = provider(None).body(&req);
+        assert_eq!(
+            body["prompt"],
+            "<|im_start|>system\nSYS<|im_end|>\n<|im_start|>user\nUSER<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(body["n_predict"], LLAMACPP_N_PREDICT);
+        assert_eq!(body["stop"], json!(["<|im_end|>", "\n\n"]));
+
+        let body = provider(Some("[INST] {system}\n{user} [/INST]")).body(&req);
+        assert_eq!(body["prompt"], "[INST] SYS\nUSER [/INST]");
+    }
+
+    #[test]
+    fn response_gives_trimmed_content() {
+        let response = provider(None)
+            .parse(
+                &json!({ "content": " fix: close the file\n", "stopped_limit": true }).to_string(),
+            )
+            .unwrap();
+        assert_eq!(response.content, "fix: close the file");
+        assert!(response.cut_off);
+
+        let empty = json!({ "content": "  " }).to_string();
+        assert!(matches!(
+            provider(None).parse(&empty),
+            Err(AiCommitError::EmptyResponse)
+        ));
+    }
+
+    #[test]
+    fn empty_stream_chunks_carry_no_delta() {
+        let event = provider(None)
+            .parse_stream_event(&json!({ "content": "" }).to_string())
+            .unwrap();
+        assert!(event.delta.is_none());
+    }
+
+    #[test]
+    fn sampling_settings_are_sent_only_when_set() {
+        let unset = GenerationOptions::default().chat_request("");
+        assert!(provider(None).body(&unset).get("temperature").is_none());
+
+        let req = GenerationOptions {
+            temperature: Some(0.5),
+            top_p: Some(0.25),
+            seed: Some(7),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let body = provider(None).body(&req);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["top_p"], 0.25);
+        assert_eq!(body["seed"], 7);
+    }
+
+    #[test]
+    fn placeholders_inside_the_prompts_are_not_expanded() {
+        let mut req = GenerationOptions::default().chat_request("");
+        req.system = "Reply to {user} in JSON: {\"subject\": ...}".to_string();
+        req.user = "USER {system}".to_string();
+
+        let body = provider(Some("{system}|{user}")).body(&req);
+        assert_eq!(
+            body["prompt"],
+            "Reply to {user} in JSON: {\"subject\": ...}|USER {system}"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+// ===================================================================
+// Offline Mock Provider
+// ===================================================================
+
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Url};
+
+use super::{ApiProvider, ChatRequest, ChatResponse, HttpSettings};
+use crate::ai_commit::{CommitFormat, GenerationOptions};
+use crate::error::AiCommitError;
+
+/// Builds a deterministic message from the diff without any network access,
+/// for demos, CI and end-to-end tests of the commit flow.
+pub struct MockProvider {
+    language: String,
+    format: CommitFormat,
+}
+
+impl MockProvider {
+    pub fn new(opts: &GenerationOptions) -> MockProvider {
+        MockProvider {
+            language: opts.language.clone(),
+            format: opts.format,
+        }
+    }
+
+    /// `update 2 files: src/lib.rs, README.md`, prefixed with `[lang]` for
+    /// languages other than English.
+    fn describe(&self, diff: &str) -> String {
+        let files: Vec<&str> = diff
+            .lines()
+            .filter_map(|line| line.strip_prefix("diff --git "))
+            .filter_map(|paths| paths.rsplit_once(" b/").map(|(_, path)| path))
+            .collect();
+        let mut message = match files.len() {
+            // A diff without file headers, e.g. a bare hunk from --stdin.
+            0 => "update files".to_string(),
+            1 => format!("update 1 file: {}", files[0]),
+            n => format!("update {} files: {}", n, files.join(", ")),
+        };
+        if self.format == CommitFormat::Conventional {
+            message = format!("chore: {}", message);
+        }
+        if self.language != "en" {
+            message = format!("[{}] {}", self.language, message);
+        }
+        message
+    }
+}
+
+#[async_trait]
+impl ApiProvider for MockProvider {
+    fn endpoint(&self, _req: &ChatRequest) -> String {
+        "mock://".to_string()
+    }
+
+    fn body(&self, _req: &ChatRequest) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        Ok(request)
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        Ok(ChatResponse {
+            content: self.describe(body),
+            alternatives: Vec::new(),
+            usage: None,
+            cut_off: false,
+            system_fingerprint: None,
+        })
+    }
+
+    /// Answers locally; the user prompt carries the diff.
+    async fn complete(&self, req: &ChatRequest) -> Result<ChatResponse, AiCommitError> {
+        self.parse(&req.user)
+    }
+
+    async fn list_models(&self, _http: &HttpSettings) -> Result<Vec<String>, AiCommitError> {
+        Ok(vec!["mock".to_string()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str =
+        "diff --git a/src/lib.rs b/src/lib.rs\n+x\ndiff --git a/README.md b/README.md\n+y\n";
+
+    fn provider(language: &str, format: CommitFormat) -> MockProvider {
+        MockProvider::new(&GenerationOptions {
+            language: language.to_string(),
+            format,
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn request_body_is_empty() {
+        let req = GenerationOptions::default().chat_request(DIFF);
+        assert!(provider("en", CommitFormat::Free).body(&req).is_null());
+    }
+
+    #[test]
+    fn reply_names_the_changed_files() {
+        let response = provider("en", CommitFormat::Free).parse(DIFF).unwrap();
+        assert_eq!(response.content, "update 2 files: src/lib.rs, README.md");
+
+        let one = "diff --git a/src/lib.rs b/src/lib.rs\n+x\n";
+        let response = provider("en", CommitFormat::Free).parse(one).unwrap();
+        assert_eq!(response.content, "update 1 file: src/lib.rs");
+
+        let response = provider("en", CommitFormat::Free)
+            .parse("@@ -1 +1 @@\n-a\n+b\n")
+            .unwrap();
+        assert_eq!(response.content, "update files");
+    }
+
+    #[test]
+    fn reply_shows_the_format_and_language() {
+        let response = provider("de", CommitFormat::Conventional)
+            .parse(DIFF)
+            .unwrap();
+        assert_eq!(
+            response.content,
+            "[de] chore: update 2 files: src/lib.rs, README.md"
+        );
+    }
+}
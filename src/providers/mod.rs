@@ -0,0 +1,1383 @@
+// ===================================================================
+// API Providers
+// ===================================================================
+
+mod anthropic;
+mod azure;
+mod bedrock;
+mod completions;
+mod gemini;
+mod llamacpp;
+mod mock;
+mod ollama;
+mod openai;
+mod vertex;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use async_trait::async_trait;
+use eventsource_stream::Eventsource;
+use futures_util::StreamExt;
+use regex::Regex;
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+use reqwest::{Certificate, Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, enabled, trace};
+
+use crate::ai_commit::{GenerationOptions, TokenUsage};
+use crate::error::AiCommitError;
+
+pub use anthropic::AnthropicProvider;
+pub use azure::AzureOpenAiProvider;
+pub use bedrock::BedrockProvider;
+pub use completions::CompletionsProvider;
+pub use gemini::GeminiProvider;
+pub use llamacpp::LlamaCppProvider;
+pub use mock::MockProvider;
+pub use ollama::OllamaProvider;
+pub use openai::OpenAiProvider;
+pub use vertex::{VERTEX_DEFAULT_REGION, VertexProvider};
+
+/// A Go-style duration as sent in `x-ratelimit-reset-requests`.
+static RESET_DURATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d+(\.\d+)?(h|ms|m|s))+$").unwrap());
+static RESET_PART: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d+(?:\.\d+)?)(h|ms|m|s)").unwrap());
+
+/// `<think>...</think>` blocks some reasoning models and proxies put in front
+/// of the answer.
+static THINK_BLOCK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<think>.*?</think>").unwrap());
+
+/// The API flavour spoken by the configured endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    OpenAi,
+    Anthropic,
+    Ollama,
+    Gemini,
+    Azure,
+    Bedrock,
+    /// Gemini through Google Cloud's Vertex AI.
+    Vertex,
+    /// llama.cpp's built-in server (`/completion`).
+    LlamaCpp,
+    /// Offline stand-in that derives the message from the diff.
+    Mock,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 9] = [
+        Provider::OpenAi,
+        Provider::Anthropic,
+        Provider::Ollama,
+        Provider::Gemini,
+        Provider::Azure,
+        Provider::Bedrock,
+        Provider::Vertex,
+        Provider::LlamaCpp,
+        Provider::Mock,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::OpenAi => "openai",
+            Provider::Anthropic => "anthropic",
+            Provider::Ollama => "ollama",
+            Provider::Gemini => "gemini",
+            Provider::Azure => "azure",
+            Provider::Bedrock => "bedrock",
+            Provider::Vertex => "vertex",
+            Provider::LlamaCpp => "llamacpp",
+            Provider::Mock => "mock",
+        }
+    }
+
+    /// Endpoint used when no URL is configured. Azure endpoints are
+    /// per-resource, so there is no sensible default, and Bedrock's and
+    /// Vertex AI's depend on the region.
+    pub fn default_url(&self, region: Option<&str>) -> Option<String> {
+        match self {
+            Provider::OpenAi => Some("https://api.openai.com/v1/chat/completions".to_string()),
+            Provider::Anthropic => Some("https://api.anthropic.com/v1/messages".to_string()),
+            Provider::Ollama => Some("http://localhost:11434/api/chat".to_string()),
+            Provider::Gemini => {
+                Some("https://generativelanguage.googleapis.com/v1beta".to_string())
+            }
+            Provider::Azure => None,
+            Provider::Bedrock => {
+                region.map(|region| format!("https://bedrock-runtime.{}.amazonaws.com", region))
+            }
+            Provider::Vertex => Some(format!(
+                "https://{}-aiplatform.googleapis.com/v1",
+                region.unwrap_or(VERTEX_DEFAULT_REGION)
+            )),
+            Provider::LlamaCpp => Some("http://127.0.0.1:8080/completion".to_string()),
+            Provider::Mock => Some("mock://".to_string()),
+        }
+    }
+
+    /// Model used when no model is configured.
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            Provider::OpenAi => "gpt-3.5-turbo",
+            Provider::Anthropic => "claude-3-5-haiku-latest",
+            Provider::Ollama => "llama3",
+            Provider::Gemini => "gemini-1.5-flash",
+            Provider::Azure => "gpt-35-turbo",
+            Provider::Bedrock => "anthropic.claude-3-haiku-20240307-v1:0",
+            Provider::Vertex => "gemini-1.5-flash",
+            // The server answers with whatever model it was started with.
+            Provider::LlamaCpp => "default",
+            Provider::Mock => "mock",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Provider> {
+        match name.to_lowercase().as_str() {
+            "openai" => Some(Provider::OpenAi),
+            "anthropic" | "claude" => Some(Provider::Anthropic),
+            "ollama" => Some(Provider::Ollama),
+            "gemini" | "google" => Some(Provider::Gemini),
+            "azure" | "azure-openai" => Some(Provider::Azure),
+            "bedrock" | "aws" => Some(Provider::Bedrock),
+            "vertex" | "vertex-ai" | "vertexai" => Some(Provider::Vertex),
+            "llamacpp" | "llama.cpp" | "llama-cpp" => Some(Provider::LlamaCpp),
+            "mock" => Some(Provider::Mock),
+            _ => None,
+        }
+    }
+
+    /// Guesses the provider from the endpoint URL, defaulting to OpenAI.
+    /// Local servers are taken to be Ollama unless the path looks like an
+    /// OpenAI-compatible API.
+    pub fn detect(url: &str) -> Provider {
+        let path = url.trim_end_matches('/');
+        if url.starts_with("mock://") {
+            Provider::Mock
+        } else if url.contains("api.anthropic.com") || path.ends_with("/v1/messages") {
+            Provider::Anthropic
+        } else if path.ends_with("/completion") {
+            Provider::LlamaCpp
+        } else if path.ends_with("/api/chat") || path.ends_with("/api/generate") {
+            Provider::Ollama
+        } else if url.contains("aiplatform.googleapis.com") {
+            Provider::Vertex
+        } else if url.contains("generativelanguage.googleapis.com") {
+            Provider::Gemini
+        } else if url.contains(".openai.azure.com") {
+            Provider::Azure
+        } else if url.contains("bedrock-runtime.") {
+            Provider::Bedrock
+        } else if is_local(url) && !path.contains("/v1") && !path.ends_with("/completions") {
+            Provider::Ollama
+        } else {
+            Provider::OpenAi
+        }
+    }
+
+    /// Local providers such as Ollama accept unauthenticated requests,
+    /// Bedrock signs requests with AWS credentials instead, and Vertex AI can
+    /// get a token from gcloud.
+    pub fn requires_api_key(&self) -> bool {
+        !matches!(
+            self,
+            Provider::Ollama
+                | Provider::Bedrock
+                | Provider::Vertex
+                | Provider::LlamaCpp
+                | Provider::Mock
+        )
+    }
+
+    /// Returns the backend implementation for this provider.
+    pub fn build(&self, opts: &GenerationOptions) -> Box<dyn ApiProvider> {
+        match self {
+            Provider::OpenAi => match opts.api_style {
+                ApiStyle::Chat => Box::new(OpenAiProvider::new(opts)),
+                ApiStyle::Completions => Box::new(CompletionsProvider::new(opts)),
+            },
+            Provider::Anthropic => Box::new(AnthropicProvider::new(opts)),
+            Provider::Ollama => Box::new(OllamaProvider::new(opts)),
+            Provider::Gemini => Box::new(GeminiProvider::new(opts)),
+            Provider::Azure => Box::new(AzureOpenAiProvider::new(opts)),
+            Provider::Bedrock => Box::new(BedrockProvider::new(opts)),
+            Provider::Vertex => Box::new(VertexProvider::new(opts)),
+            Provider::LlamaCpp => Box::new(LlamaCppProvider::new(opts)),
+            Provider::Mock => Box::new(MockProvider::new(opts)),
+        }
+    }
+}
+
+/// Which OpenAI-compatible API the endpoint exposes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiStyle {
+    /// `/v1/chat/completions` with a list of messages.
+    #[default]
+    Chat,
+    /// The legacy `/v1/completions` API with a single prompt string.
+    Completions,
+}
+
+impl ApiStyle {
+    pub fn from_name(name: &str) -> Option<ApiStyle> {
+        match name.to_ascii_lowercase().as_str() {
+            "chat" => Some(ApiStyle::Chat),
+            "completions" => Some(ApiStyle::Completions),
+            _ => None,
+        }
+    }
+}
+
+/// A hosted service selectable by name, so users don't have to remember its
+/// endpoint URL.
+#[derive(Debug)]
+pub struct Preset {
+    pub name: &'static str,
+    /// The wire format and auth style the service expects.
+    pub provider: Provider,
+    pub url: &'static str,
+    pub default_model: &'static str,
+    /// Extra headers sent to the preset's host, whichever way it was selected.
+    pub headers: &'static [(&'static str, &'static str)],
+    /// Environment variable holding a token to use when no key is configured.
+    pub key_env: Option<&'static str>,
+    /// Whether the API accepts a `seed`. Some reject unknown fields outright.
+    pub seed: bool,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "openrouter",
+        provider: Provider::OpenAi,
+        url: "https://openrouter.ai/api/v1/chat/completions",
+        default_model: "openai/gpt-4o-mini",
+        // OpenRouter's optional app attribution, used for rankings.
+        headers: &[
+            ("HTTP-Referer", "https://github.com/FergusAAA/ai_commit"),
+            ("X-Title", "ai_commit"),
+        ],
+        key_env: None,
+        seed: true,
+    },
+    Preset {
+        name: "groq",
+        provider: Provider::OpenAi,
+        url: "https://api.groq.com/openai/v1/chat/completions",
+        default_model: "llama-3.1-8b-instant",
+        headers: &[],
+        key_env: None,
+        seed: true,
+    },
+    Preset {
+        name: "mistral",
+        provider: Provider::OpenAi,
+        url: "https://api.mistral.ai/v1/chat/completions",
+        default_model: "mistral-small-latest",
+        headers: &[],
+        key_env: None,
+        // Mistral calls it `random_seed` and rejects unknown fields.
+        seed: false,
+    },
+    Preset {
+        name: "deepseek",
+        provider: Provider::OpenAi,
+        url: "https://api.deepseek.com/chat/completions",
+        default_model: "deepseek-chat",
+        headers: &[],
+        key_env: None,
+        seed: true,
+    },
+    Preset {
+        name: "together",
+        provider: Provider::OpenAi,
+        url: "https://api.together.xyz/v1/chat/completions",
+        default_model: "meta-llama/Meta-Llama-3.1-8B-Instruct-Turbo",
+        headers: &[],
+        key_env: None,
+        seed: true,
+    },
+    Preset {
+        name: "github",
+        provider: Provider::OpenAi,
+        url: "https://models.inference.ai.azure.com/chat/completions",
+        default_model: "gpt-4o-mini",
+        headers: &[],
+        // GitHub Models authenticates with a personal access token.
+        key_env: Some("GITHUB_TOKEN"),
+        seed: true,
+    },
+];
+
+/// Comma-separated list of provider and preset names, for error messages.
+pub fn supported_names() -> String {
+    Provider::ALL
+        .iter()
+        .map(Provider::name)
+        .chain(PRESETS.iter().map(|preset| preset.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether `name` is a built-in provider or a preset.
+pub fn is_known(name: &str) -> bool {
+    Provider::from_name(name).is_some() || find_preset(name).is_some()
+}
+
+fn find_preset(name: &str) -> Option<&'static Preset> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+/// The backend, URL and fallback model selected by config and flags.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub provider: Provider,
+    /// The provider or preset name, e.g. `groq`.
+    pub name: &'static str,
+    pub url: String,
+    pub default_model: &'static str,
+    /// Built-in headers for the endpoint's host, e.g. OpenRouter attribution.
+    pub headers: BTreeMap<String, String>,
+    /// Environment variable to take the API key from if none is configured.
+    pub key_env: Option<&'static str>,
+    /// Whether a configured `seed` may be sent.
+    pub seed: bool,
+}
+
+impl Endpoint {
+    /// Resolves the endpoint from an optional provider/preset name and URL.
+    /// An explicit URL always wins over the preset's; without a name the
+    /// provider is inferred from the URL.
+    pub fn resolve(
+        name: Option<&str>,
+        url: Option<&str>,
+        region: Option<&str>,
+    ) -> Result<Endpoint, AiCommitError> {
+        let (provider, preset) = match name {
+            Some(name) => match (Provider::from_name(name), find_preset(name)) {
+                (Some(provider), _) => (provider, None),
+                (None, Some(preset)) => (preset.provider, Some(preset)),
+                (None, None) => {
+                    return Err(AiCommitError::Config(format!(
+                        "Unknown provider '{}'. Supported: {}",
+                        name,
+                        supported_names()
+                    )));
+                }
+            },
+            None => (url.map(Provider::detect).unwrap_or(Provider::OpenAi), None),
+        };
+
+        let url = url
+            .map(str::to_string)
+            .or_else(|| preset.map(|preset| preset.url.to_string()))
+            .or_else(|| provider.default_url(region));
+        let url = match url {
+            Some(url) => url,
+            None if provider == Provider::Bedrock => {
+                return Err(AiCommitError::Config(
+                    "AWS region not set. Please run `ai_commit config set-region <REGION>` or set AWS_REGION"
+                        .to_string(),
+                ));
+            }
+            None => {
+                return Err(AiCommitError::Config(format!(
+                    "Provider '{}' has no default endpoint. Please run `ai_commit config set-url <URL>`",
+                    provider.name()
+                )));
+            }
+        };
+
+        Ok(Endpoint {
+            provider,
+            name: preset.map_or(provider.name(), |preset| preset.name),
+            default_model: preset.map_or(provider.default_model(), |preset| preset.default_model),
+            headers: preset_for_url(&url)
+                .iter()
+                .flat_map(|preset| preset.headers)
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            key_env: preset
+                .or_else(|| preset_for_url(&url))
+                .and_then(|preset| preset.key_env),
+            seed: preset
+                .or_else(|| preset_for_url(&url))
+                .is_none_or(|preset| preset.seed),
+            url,
+        })
+    }
+}
+
+/// Completes a base URL such as `https://api.groq.com/openai/v1` with the API
+/// path `path`. URLs whose path already ends in one of `complete` are used
+/// verbatim, as are ones that don't parse. Any query string is kept.
+pub(crate) fn with_api_path(url: &str, path: &str, complete: &[&str]) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let base = parsed.path().trim_end_matches('/').to_string();
+    if complete.iter().any(|suffix| base.ends_with(suffix)) {
+        return url.to_string();
+    }
+    parsed.set_path(&format!("{}{}", base, path));
+    parsed.to_string()
+}
+
+/// The host part of `url`, used to scope keys and headers.
+pub fn url_host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_string)
+}
+
+/// `url` with any password replaced by `****`, for display.
+pub fn redact_password(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("****"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Hides everything before the `@` of a URL that may not parse, so a
+/// malformed proxy URL can be reported without its password.
+fn hide_credentials(url: &str) -> String {
+    match url.rsplit_once('@') {
+        Some((credentials, host)) => {
+            let scheme = credentials
+                .split_once("://")
+                .map_or("", |(scheme, _)| scheme);
+            let separator = if scheme.is_empty() { "" } else { "://" };
+            format!("{}{}****@{}", scheme, separator, host)
+        }
+        None => url.to_string(),
+    }
+}
+
+/// Environment variables reqwest takes a proxy from.
+const PROXY_VARS: &[&str] = &[
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+/// Checks that `proxy` is `none` or an http(s) URL with a host. As with
+/// reqwest, a URL without a scheme is taken to be `http://`.
+pub fn check_proxy(proxy: &str) -> Result<(), AiCommitError> {
+    if proxy == "none" {
+        return Ok(());
+    }
+    let url = if proxy.contains("://") {
+        proxy.to_string()
+    } else {
+        format!("http://{}", proxy)
+    };
+    match Url::parse(&url) {
+        Ok(parsed)
+            if matches!(parsed.scheme(), "http" | "https") && parsed.host_str().is_some() =>
+        {
+            Ok(())
+        }
+        _ => Err(AiCommitError::Config(format!(
+            "Invalid proxy URL '{}'. Expected e.g. http://proxy.corp:8080",
+            hide_credentials(proxy)
+        ))),
+    }
+}
+
+/// Checks the proxy reqwest would pick up from the environment, which it
+/// would otherwise silently ignore if malformed.
+pub fn check_proxy_env() -> Result<(), AiCommitError> {
+    for var in PROXY_VARS {
+        if let Ok(value) = env::var(var)
+            && !value.is_empty()
+            && check_proxy(&value).is_err()
+        {
+            return Err(AiCommitError::Config(format!(
+                "Invalid proxy URL '{}' in {}. Expected e.g. http://proxy.corp:8080",
+                hide_credentials(&value),
+                var
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `url` points at this machine.
+fn is_local(url: &str) -> bool {
+    matches!(
+        url_host(url).as_deref(),
+        Some("localhost" | "127.0.0.1" | "[::1]")
+    )
+}
+
+/// The preset serving `url`'s host. Matching on the host means preset
+/// headers and key variables apply even when the URL was configured by hand,
+/// and never leak to unrelated endpoints.
+fn preset_for_url(url: &str) -> Option<&'static Preset> {
+    let host = url_host(url)?;
+    PRESETS
+        .iter()
+        .find(|preset| url_host(preset.url).as_deref() == Some(host.as_str()))
+}
+
+/// Upper bound on a whole request, unless configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Upper bound on establishing the connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Transport settings for every request to the endpoint.
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    /// Limit on the whole request, including reading the response.
+    pub timeout: Duration,
+    /// Proxy URL, possibly with credentials, or `none` to ignore the proxy
+    /// environment variables. reqwest's `HTTPS_PROXY` handling applies
+    /// when unset.
+    pub proxy: Option<String>,
+    /// PEM file with an extra CA to trust, e.g. a company root.
+    pub ca_cert: Option<PathBuf>,
+    /// Accept any certificate. Only for lab setups.
+    pub insecure: bool,
+    /// Client-side limit on requests per minute, shared by all runs.
+    pub requests_per_minute: Option<u32>,
+    /// Clients built from these settings. Shared by every clone, so retries,
+    /// fallback models and extra candidates reuse open connections.
+    pub(crate) clients: Arc<Mutex<Clients>>,
+}
+
+/// One client for servers on this machine and one for everything else,
+/// each built on first use.
+#[derive(Debug, Default)]
+pub(crate) struct Clients {
+    local: Option<Client>,
+    remote: Option<Client>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> HttpSettings {
+        HttpSettings {
+            timeout: DEFAULT_TIMEOUT,
+            proxy: None,
+            ca_cert: None,
+            insecure: false,
+            requests_per_minute: None,
+            clients: Arc::default(),
+        }
+    }
+}
+
+impl HttpSettings {
+    /// A copy with a different overall timeout and its own clients.
+    pub fn with_timeout(&self, timeout: Duration) -> HttpSettings {
+        HttpSettings {
+            timeout,
+            clients: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// The client for requests to `url`, built once and then reused.
+    /// Servers on this machine are always reached directly, bypassing any
+    /// proxy.
+    pub fn client(&self, url: &Url) -> Result<Client, AiCommitError> {
+        let local = is_local(url.as_str());
+        let mut clients = self.clients.lock().unwrap();
+        let slot = if local {
+            &mut clients.local
+        } else {
+            &mut clients.remote
+        };
+        if let Some(client) = slot {
+            return Ok(client.clone());
+        }
+        let client = self.build_client(local)?;
+        *slot = Some(client.clone());
+        Ok(client)
+    }
+
+    fn build_client(&self, local: bool) -> Result<Client, AiCommitError> {
+        let mut builder = Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(CONNECT_TIMEOUT.min(self.timeout));
+        match self.proxy.as_deref() {
+            _ if local => builder = builder.no_proxy(),
+            Some("none") => builder = builder.no_proxy(),
+            Some(proxy) => {
+                let proxy = Proxy::all(proxy).map_err(|_| {
+                    AiCommitError::Config(format!("Invalid proxy URL '{}'", redact_password(proxy)))
+                })?;
+                // NO_PROXY applies to a configured proxy too.
+                builder = builder.proxy(proxy.no_proxy(NoProxy::from_env()));
+            }
+            None => {}
+        }
+        if let Some(path) = &self.ca_cert {
+            let pem = fs::read(path).map_err(|e| {
+                AiCommitError::Config(format!(
+                    "Failed to read CA certificate {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            let cert = Certificate::from_pem(&pem).map_err(|e| {
+                AiCommitError::Config(format!(
+                    "Failed to parse CA certificate {} (expected PEM): {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Describes timeouts in terms of `url`'s host and the configured limit;
+    /// other errors pass through. Only the host is named, and the URL is
+    /// stripped from the error, as it can carry credentials.
+    pub fn describe_error(&self, err: reqwest::Error, url: &Url) -> AiCommitError {
+        let err = err.without_url();
+        if !err.is_timeout() {
+            return AiCommitError::Network(err);
+        }
+        if err.is_connect() {
+            AiCommitError::Timeout {
+                message: format!(
+                    "Could not connect to {} within {}. Check the URL, your network and any proxy.",
+                    url.host_str().unwrap_or_default(),
+                    format_wait(CONNECT_TIMEOUT.min(self.timeout))
+                ),
+                connect: true,
+            }
+        } else {
+            AiCommitError::Timeout {
+                message: format!(
+                    "Request to {} timed out after {} (increase with --timeout).",
+                    url.host_str().unwrap_or_default(),
+                    format_wait(self.timeout)
+                ),
+                connect: false,
+            }
+        }
+    }
+}
+
+/// Every sampling setting a request can carry.
+pub const SAMPLING_SETTINGS: &[&str] = &[
+    "temperature",
+    "top_p",
+    "frequency_penalty",
+    "presence_penalty",
+    "seed",
+];
+
+/// A provider-agnostic chat request.
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub system: String,
+    pub user: String,
+    /// Ask for the reply as server-sent events.
+    pub stream: bool,
+    /// Number of alternative replies to generate, for backends that support
+    /// returning several in one response.
+    pub n: u32,
+    /// Sampling settings, sent to backends that take them (see
+    /// [`ApiProvider::sampling_settings`]).
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub frequency_penalty: Option<f64>,
+    pub presence_penalty: Option<f64>,
+    /// Asks the backend for a repeatable sample.
+    pub seed: Option<u64>,
+    /// Stop sequences, for backends that take them.
+    pub stop: Vec<String>,
+    /// Constrain the reply to the `CommitParts` JSON schema, where the API
+    /// supports it.
+    pub structured: bool,
+    /// Configured reply length limit, if any.
+    pub max_tokens: Option<u32>,
+    /// Target is a reasoning model that only accepts user messages.
+    pub reasoning: bool,
+    /// Extra HTTP headers for this endpoint.
+    pub headers: BTreeMap<String, String>,
+    pub http: HttpSettings,
+}
+
+/// The parts of a provider response the rest of the tool cares about.
+#[derive(Debug, Clone)]
+pub struct ChatResponse {
+    pub content: String,
+    /// Further replies when more than one was requested.
+    pub alternatives: Vec<Alternative>,
+    /// Token counts, when the API reports them.
+    pub usage: Option<TokenUsage>,
+    /// The reply stopped at the token limit rather than where the model
+    /// meant to end it.
+    pub cut_off: bool,
+    /// Identifies the backend configuration that served the request, which
+    /// changes when a seeded reply may stop being repeatable.
+    pub system_fingerprint: Option<String>,
+}
+
+impl ChatResponse {
+    /// The reply followed by its alternatives.
+    pub fn into_alternatives(self) -> Vec<Alternative> {
+        let first = Alternative {
+            content: self.content,
+            cut_off: self.cut_off,
+        };
+        std::iter::once(first).chain(self.alternatives).collect()
+    }
+}
+
+/// One of several replies to a request for `n` of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alternative {
+    pub content: String,
+    /// The reply stopped at the token limit.
+    pub cut_off: bool,
+}
+
+/// What one server-sent event of a streamed reply carries.
+#[derive(Debug, Default)]
+pub struct StreamEvent {
+    /// The next piece of the reply.
+    pub delta: Option<String>,
+    /// Token counts, which OpenAI-style APIs send in a final chunk.
+    pub usage: Option<TokenUsage>,
+    /// The reply stopped at the token limit.
+    pub cut_off: bool,
+}
+
+/// A chat backend. Implementations own their wire format and authentication;
+/// sending, status handling and error reporting are shared.
+#[async_trait]
+pub trait ApiProvider: Send + Sync {
+    /// The URL the request is posted to.
+    fn endpoint(&self, req: &ChatRequest) -> String;
+
+    /// The JSON body for this provider's API.
+    fn body(&self, req: &ChatRequest) -> serde_json::Value;
+
+    /// Attaches credentials. `body` is the exact payload, for request signing.
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        url: &Url,
+        body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError>;
+
+    /// Extracts the reply from a successful response body.
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError>;
+
+    /// The sampling settings the API takes, out of `temperature`, `top_p`,
+    /// `frequency_penalty`, `presence_penalty` and `seed`. The rest are left
+    /// out of the body, with a warning.
+    fn sampling_settings(&self) -> &'static [&'static str] {
+        SAMPLING_SETTINGS
+    }
+
+    /// Whether `ChatRequest::model` picks the model. It doesn't where the
+    /// URL or an Azure deployment names one, so fallback models can't be
+    /// tried there.
+    fn honours_model(&self) -> bool {
+        true
+    }
+
+    /// Whether the provider honours `ChatRequest::n`. Others are called once
+    /// per candidate instead.
+    fn supports_multiple_choices(&self) -> bool {
+        false
+    }
+
+    /// Whether the provider can stream its reply as server-sent events.
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+
+    /// Reads the text delta, and any usage or finish reason, carried by one
+    /// server-sent event.
+    fn parse_stream_event(&self, _data: &str) -> Result<StreamEvent, AiCommitError> {
+        Ok(StreamEvent::default())
+    }
+
+    /// The URL that lists available models, if the API has one.
+    fn models_endpoint(&self) -> Option<String> {
+        None
+    }
+
+    /// Extracts model IDs from a models listing. Defaults to the OpenAI
+    /// `data[].id` shape.
+    fn parse_models(&self, body: &str) -> Result<Vec<String>, AiCommitError> {
+        Ok(parse_json::<ModelList>(body)?
+            .data
+            .into_iter()
+            .map(|model| model.id)
+            .collect())
+    }
+
+    async fn complete(&self, req: &ChatRequest) -> Result<ChatResponse, AiCommitError> {
+        execute(self, req).await
+    }
+
+    async fn list_models(&self, http: &HttpSettings) -> Result<Vec<String>, AiCommitError> {
+        fetch_models(self, http).await
+    }
+
+    /// Like `complete`, but hands each text delta to `on_delta` as it arrives.
+    async fn complete_streaming(
+        &self,
+        req: &ChatRequest,
+        on_delta: &mut (dyn for<'d> FnMut(&'d str) + Send),
+    ) -> Result<ChatResponse, AiCommitError> {
+        execute_stream(self, req, on_delta).await
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn new(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelEntry {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// A string such as `model_not_found`, though some gateways send numbers.
+    code: Option<serde_json::Value>,
+}
+
+/// Sends `req` through `provider`, surfacing the raw body on HTTP failures.
+pub(crate) async fn execute<P: ApiProvider + ?Sized>(
+    provider: &P,
+    req: &ChatRequest,
+) -> Result<ChatResponse, AiCommitError> {
+    let (res, url) = send(provider, req).await?;
+    let body = res
+        .text()
+        .await
+        .map_err(|e| req.http.describe_error(e, &url))?;
+    trace!("Response body: {}", body);
+    provider.parse(&body)
+}
+
+/// Sends a streaming request and accumulates the deltas into one reply.
+pub(crate) async fn execute_stream<P: ApiProvider + ?Sized>(
+    provider: &P,
+    req: &ChatRequest,
+    on_delta: &mut (dyn FnMut(&str) + Send),
+) -> Result<ChatResponse, AiCommitError> {
+    let (res, url) = send(provider, req).await?;
+    let mut events = res.bytes_stream().eventsource();
+    let mut content = String::new();
+    let mut reasoning = ReasoningFilter::default();
+    let mut usage = None;
+    let mut cut_off = false;
+    while let Some(event) = events.next().await {
+        let event = event.map_err(|e| match e {
+            eventsource_stream::EventStreamError::Transport(e) => req.http.describe_error(e, &url),
+            e => AiCommitError::Stream(e.to_string()),
+        })?;
+        trace!("Stream event: {}", event.data);
+        // OpenAI-style streams end with a literal `[DONE]` sentinel.
+        if event.data.trim() == "[DONE]" {
+            break;
+        }
+        // One garbled chunk shouldn't throw away everything streamed so far.
+        match provider.parse_stream_event(&event.data) {
+            Ok(event) => {
+                if let Some(delta) = event.delta {
+                    content.push_str(&delta);
+                    if let Some(visible) = reasoning.push(&delta) {
+                        on_delta(&visible);
+                    }
+                }
+                usage = event.usage.or(usage);
+                cut_off |= event.cut_off;
+            }
+            Err(e) => debug!("Skipping unreadable stream event: {}", e),
+        }
+    }
+    let content = strip_reasoning(&content);
+    if content.is_empty() {
+        return Err(AiCommitError::EmptyResponse);
+    }
+    Ok(ChatResponse {
+        content,
+        alternatives: Vec::new(),
+        usage,
+        cut_off,
+        system_fingerprint: None,
+    })
+}
+
+/// Builds, authorizes and sends the HTTP request, turning non-2xx statuses
+/// into `ApiError`. Returns the response with the URL it came from.
+async fn send<P: ApiProvider + ?Sized>(
+    provider: &P,
+    req: &ChatRequest,
+) -> Result<(Response, Url), AiCommitError> {
+    let endpoint = provider.endpoint(req);
+    let url = Url::parse(&endpoint)
+        .map_err(|e| AiCommitError::Config(format!("Invalid API URL '{}': {}", endpoint, e)))?;
+    let body =
+        serde_json::to_vec(&provider.body(req)).map_err(|source| AiCommitError::Serialization {
+            source,
+            body: String::new(),
+        })?;
+
+    let mut request = req
+        .http
+        .client(&url)?
+        .post(url.clone())
+        .header(CONTENT_TYPE, "application/json");
+    for (name, value) in &req.headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    debug!(
+        "POST {} (model {})",
+        redact_password(url.as_str()),
+        req.model
+    );
+    debug!("Request body: {} bytes", body.len());
+    trace!("Request body: {}", String::from_utf8_lossy(&body));
+    let (client, request) = provider
+        .authorize(request, &url, &body)?
+        .body(body)
+        .build_split();
+    let request = request?;
+    if enabled!(tracing::Level::DEBUG) {
+        let names: Vec<&str> = request.headers().keys().map(|name| name.as_str()).collect();
+        // Values can carry keys and tokens, so only names are logged.
+        debug!("Request headers: {}", names.join(", "));
+    }
+    if let Some(per_minute) = req.http.requests_per_minute {
+        crate::throttle::wait_for_slot(per_minute).await?;
+    }
+    let started = Instant::now();
+    let res = client
+        .execute(request)
+        .await
+        .map_err(|e| req.http.describe_error(e, &url))?;
+
+    let status = res.status();
+    debug!("Response: {} after {:.2?}", status, started.elapsed());
+    if !status.is_success() {
+        let (retry_after, limits) = if status == StatusCode::TOO_MANY_REQUESTS {
+            (
+                rate_limit_reset(res.headers()),
+                rate_limit_remaining(res.headers()),
+            )
+        } else {
+            (None, None)
+        };
+        let body = res
+            .text()
+            .await
+            .map_err(|e| req.http.describe_error(e, &url))?;
+        trace!("Response body: {}", body);
+        let mut body = error_message(body);
+        if let Some(wait) = retry_after {
+            body = format!("{} (rate limit resets in {})", body, format_wait(wait));
+        }
+        if let Some(limits) = limits {
+            body = format!("{} [{}]", body, limits);
+        }
+        return Err(AiCommitError::ApiError {
+            status: status.as_u16(),
+            body,
+            retry_after,
+        });
+    }
+    Ok((res, url))
+}
+
+/// Fetches the model listing, telling authentication failures apart from
+/// endpoints that have no listing at all.
+pub(crate) async fn fetch_models<P: ApiProvider + ?Sized>(
+    provider: &P,
+    http: &HttpSettings,
+) -> Result<Vec<String>, AiCommitError> {
+    let endpoint = provider.models_endpoint().ok_or_else(|| {
+        AiCommitError::Config("This provider does not support listing models.".to_string())
+    })?;
+    let url = Url::parse(&endpoint)
+        .map_err(|e| AiCommitError::Config(format!("Invalid API URL '{}': {}", endpoint, e)))?;
+
+    debug!("GET {}", redact_password(url.as_str()));
+    let request = http.client(&url)?.get(url.clone());
+    let res = provider
+        .authorize(request, &url, b"")?
+        .send()
+        .await
+        .map_err(|e| http.describe_error(e, &url))?;
+    let status = res.status().as_u16();
+    let body = res.text().await.map_err(|e| http.describe_error(e, &url))?;
+    debug!("Response: {}", status);
+    trace!("Response body: {}", body);
+    match status {
+        200..=299 => provider.parse_models(&body),
+        401 | 403 => Err(AiCommitError::Credentials(format!(
+            "Authentication failed while listing models (status {}): {}. Check your API key.",
+            status,
+            error_message(body)
+        ))),
+        404 | 405 | 501 => Err(AiCommitError::Config(format!(
+            "{} does not provide a models listing (status {}).",
+            endpoint, status
+        ))),
+        _ => Err(AiCommitError::ApiError {
+            status,
+            body: error_message(body),
+            retry_after: None,
+        }),
+    }
+}
+
+/// When a rate limit lifts, from `Retry-After` (seconds), `x-ratelimit-reset`
+/// (Unix time, as sent by GitHub Models) or OpenAI's
+/// `x-ratelimit-reset-requests` (a duration such as `6m0s`).
+fn rate_limit_reset(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+    if let Some(seconds) = header("retry-after").and_then(|value| value.parse::<f64>().ok()) {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    if let Some(reset) = header("x-ratelimit-reset").and_then(|value| value.parse::<i64>().ok()) {
+        let remaining = reset - chrono::Utc::now().timestamp();
+        return Some(Duration::from_secs(remaining.max(0) as u64));
+    }
+    header("x-ratelimit-reset-requests").and_then(parse_reset_duration)
+}
+
+/// What is left of OpenAI-style request and token limits, e.g.
+/// `0 of 500 requests, 1200 of 40000 tokens left`.
+fn rate_limit_remaining(headers: &HeaderMap) -> Option<String> {
+    let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+    let parts: Vec<String> = ["requests", "tokens"]
+        .iter()
+        .filter_map(|unit| {
+            let remaining = header(&format!("x-ratelimit-remaining-{}", unit))?;
+            Some(match header(&format!("x-ratelimit-limit-{}", unit)) {
+                Some(limit) => format!("{} of {} {}", remaining, limit, unit),
+                None => format!("{} {}", remaining, unit),
+            })
+        })
+        .collect();
+    (!parts.is_empty()).then(|| format!("{} left", parts.join(", ")))
+}
+
+/// Parses durations such as `6m0s`, `1.5s` or `250ms`.
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    if !RESET_DURATION.is_match(value) {
+        return None;
+    }
+    let seconds = RESET_PART
+        .captures_iter(value)
+        .map(|part| {
+            let amount = part[1].parse::<f64>().unwrap_or_default();
+            amount
+                * match &part[2] {
+                    "h" => 3600.0,
+                    "m" => 60.0,
+                    "s" => 1.0,
+                    _ => 0.001,
+                }
+        })
+        .sum::<f64>();
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// A wait for messages, e.g. `42s` or `250ms`.
+pub fn format_wait(wait: Duration) -> String {
+    if wait < Duration::from_secs(1) {
+        format!("{}ms", wait.as_millis())
+    } else {
+        format!("{}s", wait.as_secs_f64().ceil())
+    }
+}
+
+/// OpenAI-style gateways (including Azure) wrap the reason in `error.message`,
+/// with the error type and code alongside. Other bodies are kept as they are.
+fn error_message(body: String) -> String {
+    let Ok(ApiErrorBody { error }) = serde_json::from_str::<ApiErrorBody>(&body) else {
+        return body;
+    };
+    let code = match error.code {
+        Some(serde_json::Value::String(code)) => Some(code),
+        Some(serde_json::Value::Number(code)) => Some(code.to_string()),
+        _ => None,
+    };
+    let details: Vec<String> = error.kind.into_iter().chain(code).collect();
+    if details.is_empty() {
+        error.message
+    } else {
+        format!("({}) {}", details.join(", "), error.message)
+    }
+}
+
+/// Deserializes a response body, keeping the raw text for error reports.
+pub(crate) fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T, AiCommitError> {
+    serde_json::from_str::<T>(body).map_err(|source| AiCommitError::Serialization {
+        source,
+        body: body.to_string(),
+    })
+}
+
+pub(crate) fn first_message(message: Option<String>) -> Result<ChatResponse, AiCommitError> {
+    message
+        .map(|content| ChatResponse {
+            content,
+            alternatives: Vec::new(),
+            usage: None,
+            cut_off: false,
+            system_fingerprint: None,
+        })
+        .ok_or(AiCommitError::EmptyResponse)
+}
+
+/// Removes inline `<think>` reasoning, leaving only the final answer.
+pub(crate) fn strip_reasoning(content: &str) -> String {
+    THINK_BLOCK.replace_all(content, "").trim().to_string()
+}
+
+/// Holds back `<think>` reasoning in a stream, so that only the answer is
+/// echoed as it arrives.
+#[derive(Default)]
+struct ReasoningFilter {
+    received: String,
+    passed_on: usize,
+}
+
+impl ReasoningFilter {
+    /// Adds `delta` and returns the newly visible text, if any.
+    fn push(&mut self, delta: &str) -> Option<String> {
+        self.received.push_str(delta);
+        let visible = THINK_BLOCK.replace_all(&self.received, "");
+        // An unclosed block, or what may yet become its opening tag, stays
+        // hidden until more arrives.
+        let mut end = visible.find("<think>").unwrap_or(visible.len());
+        if let Some(start) = visible[..end].rfind('<')
+            && "<think>".starts_with(&visible[start..end])
+        {
+            end = start;
+        }
+        let visible = visible[..end].trim_start();
+        let new = visible.get(self.passed_on..).filter(|new| !new.is_empty());
+        let new = new.map(str::to_string);
+        self.passed_on = self.passed_on.max(visible.len());
+        new
+    }
+}
+
+/// Serializes a request struct. Plain data structs cannot fail to serialize.
+pub(crate) fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).expect("request structs always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected(url: &str) -> Provider {
+        Endpoint::resolve(None, Some(url), None).unwrap().provider
+    }
+
+    #[test]
+    fn provider_is_detected_from_the_url_when_none_is_configured() {
+        assert_eq!(detected("https://api.anthropic.com"), Provider::Anthropic);
+        assert_eq!(detected("http://localhost:11434"), Provider::Ollama);
+        assert_eq!(detected("http://localhost:8080/v1"), Provider::OpenAi);
+        assert_eq!(
+            detected("http://127.0.0.1:8080/completion"),
+            Provider::LlamaCpp
+        );
+        assert_eq!(
+            detected("https://me.openai.azure.com/openai/deployments/x"),
+            Provider::Azure
+        );
+        assert_eq!(detected("https://api.openai.com/v1"), Provider::OpenAi);
+        assert_eq!(
+            Endpoint::resolve(None, None, None).unwrap().provider,
+            Provider::OpenAi
+        );
+    }
+
+    const CHAT: &[&str] = &["/chat/completions", "/completions"];
+
+    #[test]
+    fn api_path_is_added_to_base_urls() {
+        assert_eq!(
+            with_api_path("https://api.groq.com/openai/v1", "/chat/completions", CHAT),
+            "https://api.groq.com/openai/v1/chat/completions"
+        );
+        assert_eq!(
+            with_api_path("https://api.groq.com/openai/v1/", "/chat/completions", CHAT),
+            "https://api.groq.com/openai/v1/chat/completions"
+        );
+    }
+
+    #[test]
+    fn complete_urls_are_used_verbatim() {
+        for url in [
+            "https://api.openai.com/v1/chat/completions",
+            "https://api.openai.com/v1/chat/completions/",
+            "http://localhost:8000/v1/completions",
+            "not a url",
+        ] {
+            assert_eq!(with_api_path(url, "/chat/completions", CHAT), url);
+        }
+    }
+
+    #[test]
+    fn api_path_goes_before_the_query_string() {
+        assert_eq!(
+            with_api_path(
+                "https://me.openai.azure.com/openai/deployments/gpt?api-version=2024-10-21",
+                "/chat/completions",
+                CHAT
+            ),
+            "https://me.openai.azure.com/openai/deployments/gpt/chat/completions?api-version=2024-10-21"
+        );
+        let complete = "https://me.openai.azure.com/openai/deployments/gpt/chat/completions?api-version=2024-10-21";
+        assert_eq!(with_api_path(complete, "/chat/completions", CHAT), complete);
+    }
+
+    #[test]
+    fn azure_endpoint_always_carries_an_api_version() {
+        let endpoint = |url: &str| {
+            let opts = GenerationOptions {
+                url: url.to_string(),
+                model: "gpt-4o".to_string(),
+                azure_api_version: Some("2024-10-21".to_string()),
+                ..GenerationOptions::default()
+            };
+            Provider::Azure
+                .build(&opts)
+                .endpoint(&opts.chat_request(""))
+        };
+
+        assert_eq!(
+            endpoint("https://me.openai.azure.com/"),
+            "https://me.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21"
+        );
+        assert_eq!(
+            endpoint("https://me.openai.azure.com/openai/deployments/d/chat/completions"),
+            "https://me.openai.azure.com/openai/deployments/d/chat/completions?api-version=2024-10-21"
+        );
+        assert_eq!(
+            endpoint(
+                "https://me.openai.azure.com/openai/deployments/d/chat/completions?api-version=2023-05-15"
+            ),
+            "https://me.openai.azure.com/openai/deployments/d/chat/completions?api-version=2023-05-15"
+        );
+    }
+
+    fn echoed(deltas: &[&str]) -> String {
+        let mut filter = ReasoningFilter::default();
+        deltas
+            .iter()
+            .filter_map(|delta| filter.push(delta))
+            .collect()
+    }
+
+    #[test]
+    fn reasoning_filter_hides_think_blocks_split_across_deltas() {
+        assert_eq!(
+            echoed(&[
+                "<thi",
+                "nk>plan the",
+                " message</th",
+                "ink>\n\nfeat: ",
+                "add x"
+            ]),
+            "feat: add x"
+        );
+    }
+
+    #[test]
+    fn reasoning_filter_passes_plain_text_and_lone_brackets() {
+        assert_eq!(
+            echoed(&["fix: handle ", "a < b", " and <tag>"]),
+            "fix: handle a < b and <tag>"
+        );
+    }
+
+    #[test]
+    fn reasoning_filter_never_shows_an_unclosed_block() {
+        assert_eq!(echoed(&["feat: x\n", "<think>still thinking"]), "feat: x\n");
+    }
+
+    #[tokio::test]
+    async fn timeout_errors_name_only_the_host() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .respond_with(wiremock::ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+        let http = HttpSettings {
+            timeout: Duration::from_millis(100),
+            ..HttpSettings::default()
+        };
+        let mut url = Url::parse(&format!("{}/v1/models?key=secret", server.uri())).unwrap();
+        url.set_username("user").unwrap();
+        url.set_password(Some("hunter2")).unwrap();
+
+        let err = Client::builder()
+            .timeout(http.timeout)
+            .build()
+            .unwrap()
+            .get(url.clone())
+            .send()
+            .await
+            .unwrap_err();
+        let message = http.describe_error(err, &url).to_string();
+
+        assert!(
+            message.starts_with("Request to 127.0.0.1 timed out"),
+            "{}",
+            message
+        );
+        assert!(!message.contains("secret") && !message.contains("hunter2"));
+    }
+}
@@ -0,0 +1,214 @@
+// ===================================================================
+// Ollama Native API
+// ===================================================================
+
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+
+use super::{ApiProvider, ChatRequest, ChatResponse, Message, first_message, parse_json, to_json};
+use crate::ai_commit::GenerationOptions;
+use crate::error::AiCommitError;
+
+#[derive(Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+/// Model parameters; `num_predict` is Ollama's reply limit.
+#[derive(Serialize, Default, PartialEq)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaResponse {
+    message: Message,
+    /// `length` when the reply hit `num_predict`.
+    #[serde(default)]
+    done_reason: Option<String>,
+}
+
+/// `/api/generate` takes a single prompt instead of a message list.
+#[derive(Serialize)]
+struct OllamaGenerateRequest {
+    model: String,
+    system: String,
+    prompt: String,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaGenerateResponse {
+    response: String,
+    #[serde(default)]
+    done_reason: Option<String>,
+}
+
+/// `/api/tags` lists local models under `models[].name`.
+#[derive(Deserialize, Debug)]
+struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaModel {
+    name: String,
+}
+
+/// Local Ollama server; requests are unauthenticated. Speaks `/api/chat`
+/// unless the URL points at `/api/generate`.
+pub struct OllamaProvider {
+    url: String,
+}
+
+impl OllamaProvider {
+    pub fn new(opts: &GenerationOptions) -> OllamaProvider {
+        OllamaProvider {
+            url: opts.url.clone(),
+        }
+    }
+
+    fn uses_generate(&self) -> bool {
+        self.url.trim_end_matches('/').ends_with("/api/generate")
+    }
+
+    /// The server root, e.g. `http://localhost:11434`.
+    fn base_url(&self) -> &str {
+        let url = self.url.trim_end_matches('/');
+        url.strip_suffix("/api/chat")
+            .or_else(|| url.strip_suffix("/api/generate"))
+            .unwrap_or(url)
+    }
+}
+
+impl ApiProvider for OllamaProvider {
+    /// A bare server URL gets the chat endpoint.
+    fn endpoint(&self, _req: &ChatRequest) -> String {
+        if self.base_url() == self.url.trim_end_matches('/') {
+            format!("{}/api/chat", self.base_url())
+        } else {
+            self.url.clone()
+        }
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        let options = Some(OllamaOptions {
+            num_predict: req.max_tokens,
+            temperature: req.temperature,
+            top_p: req.top_p,
+            frequency_penalty: req.frequency_penalty,
+            presence_penalty: req.presence_penalty,
+            seed: req.seed,
+        })
+        .filter(|options| *options != OllamaOptions::default());
+        if self.uses_generate() {
+            return to_json(&OllamaGenerateRequest {
+                model: req.model.clone(),
+                system: req.system.clone(),
+                prompt: req.user.clone(),
+                stream: false,
+                options,
+            });
+        }
+        to_json(&OllamaRequest {
+            model: req.model.clone(),
+            messages: vec![
+                Message::new("system", &req.system),
+                Message::new("user", &req.user),
+            ],
+            stream: false,
+            options,
+        })
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        Ok(request)
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        let (content, done_reason) = if self.uses_generate() {
+            let response = parse_json::<OllamaGenerateResponse>(body)?;
+            (response.response, response.done_reason)
+        } else {
+            let response = parse_json::<OllamaResponse>(body)?;
+            (response.message.content, response.done_reason)
+        };
+        let mut reply = first_message(Some(content))?;
+        reply.cut_off = done_reason.as_deref() == Some("length");
+        Ok(reply)
+    }
+
+    fn models_endpoint(&self) -> Option<String> {
+        Some(format!("{}/api/tags", self.base_url()))
+    }
+
+    fn parse_models(&self, body: &str) -> Result<Vec<String>, AiCommitError> {
+        Ok(parse_json::<OllamaTags>(body)?
+            .models
+            .into_iter()
+            .map(|model| model.name)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(url: &str) -> OllamaProvider {
+        OllamaProvider::new(&GenerationOptions {
+            url: url.to_string(),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn reply_limit_is_sent_as_num_predict_only_when_set() {
+        let mut req = GenerationOptions::default().chat_request("diff --git a/x b/x\n");
+        let chat = provider("http://localhost:11434/api/chat");
+        let generate = provider("http://localhost:11434/api/generate");
+        assert!(chat.body(&req).get("options").is_none());
+
+        req.max_tokens = Some(100);
+        assert_eq!(chat.body(&req)["options"]["num_predict"], 100);
+        assert_eq!(generate.body(&req)["options"]["num_predict"], 100);
+    }
+
+    #[test]
+    fn sampling_settings_go_in_the_options() {
+        let req = GenerationOptions {
+            temperature: Some(0.5),
+            top_p: Some(0.25),
+            seed: Some(7),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let options = &provider("http://localhost:11434/api/chat").body(&req)["options"];
+        assert_eq!(options["temperature"], 0.5);
+        assert_eq!(options["top_p"], 0.25);
+        assert_eq!(options["seed"], 7);
+        assert!(options.get("num_predict").is_none());
+    }
+}
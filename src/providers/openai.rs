@@ -0,0 +1,486 @@
+// ===================================================================
+// OpenAI Chat Completions
+// ===================================================================
+
+use reqwest::{RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+
+use super::completions::CompletionsResponse;
+use super::{
+    Alternative, ApiProvider, ChatRequest, ChatResponse, Message, StreamEvent, first_message,
+    parse_json, strip_reasoning, to_json, with_api_path,
+};
+use crate::ai_commit::{GenerationOptions, TokenUsage};
+use crate::error::AiCommitError;
+use crate::structured;
+
+#[derive(Serialize)]
+pub(crate) struct OpenAiRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Reasoning models reject `max_tokens` and count their hidden reasoning
+    /// against this limit instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+}
+
+/// Asks for token usage in a final chunk, which streams otherwise leave out.
+#[derive(Serialize)]
+pub(crate) struct StreamOptions {
+    include_usage: bool,
+}
+
+impl StreamOptions {
+    /// `None` unless `stream` is set; the API rejects it on plain requests.
+    pub fn for_stream(stream: bool) -> Option<StreamOptions> {
+        stream.then_some(StreamOptions {
+            include_usage: true,
+        })
+    }
+}
+
+impl OpenAiRequest {
+    pub fn new(req: &ChatRequest) -> OpenAiRequest {
+        let messages = if req.reasoning {
+            // Reasoning models reject the system role, so fold the
+            // instructions into the user turn.
+            vec![Message::new(
+                "user",
+                &format!("{}\n\n{}", req.system, req.user),
+            )]
+        } else {
+            vec![
+                Message::new("system", &req.system),
+                Message::new("user", &req.user),
+            ]
+        };
+        OpenAiRequest {
+            model: req.model.clone(),
+            messages,
+            stream: req.stream,
+            stream_options: StreamOptions::for_stream(req.stream),
+            n: (req.n > 1).then_some(req.n),
+            // Reasoning models only accept the default sampling settings.
+            temperature: req.temperature.filter(|_| !req.reasoning),
+            top_p: req.top_p.filter(|_| !req.reasoning),
+            frequency_penalty: req.frequency_penalty.filter(|_| !req.reasoning),
+            presence_penalty: req.presence_penalty.filter(|_| !req.reasoning),
+            seed: req.seed,
+            // Reasoning models reject stop sequences too.
+            stop: if req.reasoning {
+                Vec::new()
+            } else {
+                req.stop.clone()
+            },
+            response_format: req.structured.then(structured::response_format),
+            // Only sent when set, as some compatible endpoints reject it.
+            // Reasoning models take `max_completion_tokens` instead.
+            max_tokens: req.max_tokens.filter(|_| !req.reasoning),
+            max_completion_tokens: req.max_tokens.filter(|_| req.reasoning),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct OpenAiResponse {
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+    #[serde(default)]
+    system_fingerprint: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    message: ResponseMessage,
+    /// `length` when the reply hit `max_tokens`.
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+/// Reasoning models such as deepseek-reasoner return their chain of thought
+/// in `reasoning_content`, separate from the answer. It is never part of the
+/// commit message.
+#[derive(Deserialize, Debug)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default, rename = "reasoning_content")]
+    _reasoning_content: Option<String>,
+}
+
+/// One `chat.completion.chunk` from a streaming response.
+#[derive(Deserialize, Debug)]
+pub(crate) struct OpenAiStreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    /// Only in the final chunk, and only with `include_usage`.
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: Delta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Delta {
+    content: Option<String>,
+}
+
+impl OpenAiStreamChunk {
+    pub fn parse_event(data: &str) -> Result<StreamEvent, AiCommitError> {
+        let chunk = parse_json::<OpenAiStreamChunk>(data)?;
+        let choice = chunk.choices.into_iter().next();
+        Ok(StreamEvent {
+            cut_off: choice
+                .as_ref()
+                .is_some_and(|choice| choice.finish_reason.as_deref() == Some("length")),
+            delta: choice.and_then(|choice| choice.delta.content),
+            usage: chunk.usage,
+        })
+    }
+}
+
+impl OpenAiResponse {
+    pub fn into_chat_response(self) -> Result<ChatResponse, AiCommitError> {
+        let mut replies = self.choices.into_iter().filter_map(|choice| {
+            Some(Alternative {
+                cut_off: choice.finish_reason.as_deref() == Some("length"),
+                content: strip_reasoning(&choice.message.content?),
+            })
+        });
+        let first = replies.next();
+        let cut_off = first.as_ref().is_some_and(|first| first.cut_off);
+        let mut response = first_message(first.map(|first| first.content))?;
+        response.alternatives = replies.collect();
+        response.usage = self.usage;
+        response.cut_off = cut_off;
+        response.system_fingerprint = self.system_fingerprint;
+        Ok(response)
+    }
+}
+
+/// Derives `.../models` from a `.../chat/completions` or `.../completions` URL.
+pub(crate) fn models_url(url: &str) -> Option<String> {
+    let url = url.trim_end_matches('/');
+    let base = url
+        .strip_suffix("/chat/completions")
+        .or_else(|| url.strip_suffix("/completions"))?;
+    Some(format!("{}/models", base))
+}
+
+pub struct OpenAiProvider {
+    url: String,
+    api_key: String,
+}
+
+impl OpenAiProvider {
+    pub fn new(opts: &GenerationOptions) -> OpenAiProvider {
+        OpenAiProvider {
+            url: with_api_path(
+                &opts.url,
+                "/chat/completions",
+                &["/chat/completions", "/completions"],
+            ),
+            api_key: opts.api_key.clone(),
+        }
+    }
+}
+
+impl ApiProvider for OpenAiProvider {
+    fn endpoint(&self, _req: &ChatRequest) -> String {
+        self.url.clone()
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        to_json(&OpenAiRequest::new(req))
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        Ok(request.bearer_auth(&self.api_key))
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        if CompletionsResponse::matches(body) {
+            return Err(AiCommitError::Config(
+                "The endpoint returned a text completion, not a chat completion. \
+                Run `ai_commit config set-api-style completions` to use the legacy completions API."
+                    .to_string(),
+            ));
+        }
+        parse_json::<OpenAiResponse>(body)?.into_chat_response()
+    }
+
+    fn models_endpoint(&self) -> Option<String> {
+        models_url(&self.url)
+    }
+
+    fn supports_multiple_choices(&self) -> bool {
+        true
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn parse_stream_event(&self, data: &str) -> Result<StreamEvent, AiCommitError> {
+        OpenAiStreamChunk::parse_event(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request() -> ChatRequest {
+        GenerationOptions {
+            model: "gpt-4o-mini".to_string(),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n")
+    }
+
+    fn provider() -> OpenAiProvider {
+        OpenAiProvider::new(&GenerationOptions {
+            url: "https://api.openai.com/v1/chat/completions".to_string(),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn request_has_system_and_user_messages_and_no_default_limit() {
+        let req = request();
+        let body = provider().body(&req);
+
+        assert_eq!(body["model"], "gpt-4o-mini");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][0]["content"], json!(req.system));
+        assert_eq!(body["messages"][1]["role"], "user");
+        assert_eq!(body["messages"][1]["content"], json!(req.user));
+        assert!(body.get("max_tokens").is_none());
+    }
+
+    #[test]
+    fn unset_options_are_left_out() {
+        let body = provider().body(&request());
+        let body = body.as_object().unwrap();
+
+        for field in [
+            "stream",
+            "n",
+            "temperature",
+            "top_p",
+            "seed",
+            "stop",
+            "response_format",
+            "max_completion_tokens",
+        ] {
+            assert!(!body.contains_key(field), "{} should be omitted", field);
+        }
+    }
+
+    #[test]
+    fn set_options_are_sent() {
+        let mut req = request();
+        req.temperature = Some(0.5);
+        req.seed = Some(7);
+        req.stop = vec!["Explanation:".to_string()];
+        req.n = 3;
+        let body = provider().body(&req);
+
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["seed"], 7);
+        assert_eq!(body["stop"], json!(["Explanation:"]));
+        assert_eq!(body["n"], 3);
+    }
+
+    #[test]
+    fn reasoning_models_get_one_user_message_and_no_sampling() {
+        let mut req = GenerationOptions {
+            model: "o3-mini".to_string(),
+            temperature: Some(0.2),
+            top_p: Some(0.9),
+            stop: vec!["Explanation:".to_string()],
+            max_tokens: Some(2000),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        assert!(req.reasoning);
+        let body = provider().body(&req);
+        let fields = body.as_object().unwrap();
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["role"], "user");
+        assert!(
+            messages[0]["content"]
+                .as_str()
+                .unwrap()
+                .starts_with(&req.system)
+        );
+        for field in ["temperature", "top_p", "stop", "max_tokens"] {
+            assert!(!fields.contains_key(field), "{} should be omitted", field);
+        }
+        assert_eq!(body["max_completion_tokens"], 2000);
+
+        req.max_tokens = None;
+        assert!(
+            !provider()
+                .body(&req)
+                .as_object()
+                .unwrap()
+                .contains_key("max_completion_tokens")
+        );
+    }
+
+    #[test]
+    fn chat_models_keep_sampling_settings() {
+        let req = GenerationOptions {
+            model: "gpt-4o".to_string(),
+            temperature: Some(0.2),
+            top_p: Some(0.9),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        assert!(!req.reasoning);
+        let body = provider().body(&req);
+
+        assert_eq!(body["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(body["temperature"], 0.2);
+        assert_eq!(body["top_p"], 0.9);
+        assert!(body.get("max_tokens").is_none());
+    }
+
+    #[test]
+    fn streamed_requests_ask_for_usage() {
+        let mut req = request();
+        req.stream = true;
+        let body = provider().body(&req);
+
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[test]
+    fn response_gives_content_usage_and_alternatives() {
+        let response = provider()
+            .parse(
+                &json!({
+                    "choices": [
+                        { "message": { "content": "feat: add parser" }, "finish_reason": "stop" },
+                        { "message": { "content": "feat: parse input" }, "finish_reason": "stop" }
+                    ],
+                    "usage": { "prompt_tokens": 12, "completion_tokens": 4, "total_tokens": 16 },
+                    "system_fingerprint": "fp_1"
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(response.content, "feat: add parser");
+        assert_eq!(
+            response.alternatives,
+            [Alternative {
+                content: "feat: parse input".to_string(),
+                cut_off: false
+            }]
+        );
+        assert_eq!(response.usage.unwrap().total_tokens, 16);
+        assert!(!response.cut_off);
+        assert_eq!(response.system_fingerprint.as_deref(), Some("fp_1"));
+    }
+
+    #[test]
+    fn length_finish_reason_marks_the_reply_cut_off() {
+        let response = provider()
+            .parse(
+                &json!({
+                    "choices": [{ "message": { "content": "feat: add" }, "finish_reason": "length" }]
+                })
+                .to_string(),
+            )
+            .unwrap();
+
+        assert!(response.cut_off);
+        assert!(response.usage.is_none());
+    }
+
+    #[test]
+    fn response_without_choices_is_an_error() {
+        assert!(provider().parse(r#"{"choices": []}"#).is_err());
+        assert!(provider().parse("not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_skips_malformed_chunks_and_stops_at_done() {
+        use crate::providers::execute_stream;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let body = [
+            r#"data: {"choices":[{"delta":{"content":"feat: "}}]}"#,
+            "data: {not json",
+            r#"data: {"choices":[{"delta":{"content":"stream"}}]}"#,
+            r#"data: {"choices":[{"delta":{},"finish_reason":"length"}],"usage":null}"#,
+            r#"data: {"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":3,"total_tokens":12}}"#,
+            "data: [DONE]",
+            r#"data: {"choices":[{"delta":{"content":" ignored"}}]}"#,
+        ]
+        .map(|line| format!("{}\n\n", line))
+        .concat();
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let provider = OpenAiProvider::new(&GenerationOptions {
+            url: format!("{}/v1/chat/completions", server.uri()),
+            ..GenerationOptions::default()
+        });
+        let mut req = request();
+        req.stream = true;
+        let mut echoed = String::new();
+        let response = execute_stream(&provider, &req, &mut |delta| echoed.push_str(delta))
+            .await
+            .unwrap();
+
+        assert_eq!(response.content, "feat: stream");
+        assert_eq!(echoed, "feat: stream");
+        assert_eq!(response.usage.unwrap().total_tokens, 12);
+        assert!(response.cut_off);
+    }
+}
@@ -0,0 +1,196 @@
+// ===================================================================
+// Google Vertex AI
+// ===================================================================
+
+use std::process::Command;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use reqwest::header::AUTHORIZATION;
+use reqwest::{RequestBuilder, Url};
+
+use super::{ApiProvider, ChatRequest, ChatResponse, GeminiProvider, execute};
+use crate::ai_commit::GenerationOptions;
+use crate::error::AiCommitError;
+
+/// Location used when no region is configured.
+pub const VERTEX_DEFAULT_REGION: &str = "us-central1";
+
+/// Gemini models served through Vertex AI. Requests carry an OAuth access
+/// token, taken from the configured API key or minted with `gcloud`.
+pub struct VertexProvider {
+    /// Vertex speaks Gemini's `generateContent` wire format.
+    gemini: GeminiProvider,
+    url: String,
+    project: Option<String>,
+    region: String,
+    token: Mutex<String>,
+}
+
+impl VertexProvider {
+    pub fn new(opts: &GenerationOptions) -> VertexProvider {
+        VertexProvider {
+            gemini: GeminiProvider::new(opts),
+            url: opts.url.clone(),
+            project: opts.project.clone(),
+            region: opts
+                .region
+                .clone()
+                .unwrap_or_else(|| VERTEX_DEFAULT_REGION.to_string()),
+            token: Mutex::new(opts.api_key.clone()),
+        }
+    }
+
+    /// Replaces the cached token with a fresh one from `gcloud`.
+    fn refresh_token(&self) -> Result<String, AiCommitError> {
+        let token = gcloud_access_token()?;
+        *self.token.lock().unwrap() = token.clone();
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl ApiProvider for VertexProvider {
+    /// Builds the publisher model endpoint from a Vertex base URL, leaving
+    /// fully-qualified endpoints untouched.
+    fn endpoint(&self, req: &ChatRequest) -> String {
+        if self.url.contains(":generateContent") {
+            return self.url.clone();
+        }
+        format!(
+            "{}/projects/{}/locations/{}/publishers/google/models/{}:generateContent",
+            self.url.trim_end_matches('/'),
+            self.project.as_deref().unwrap_or_default(),
+            self.region,
+            req.model
+        )
+    }
+
+    fn honours_model(&self) -> bool {
+        !self.url.contains(":generateContent")
+    }
+
+    fn body(&self, req: &ChatRequest) -> serde_json::Value {
+        self.gemini.body(req)
+    }
+
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        _url: &Url,
+        _body: &[u8],
+    ) -> Result<RequestBuilder, AiCommitError> {
+        let cached = self.token.lock().unwrap().clone();
+        let token = if cached.is_empty() {
+            self.refresh_token()?
+        } else {
+            cached
+        };
+        Ok(request.header(AUTHORIZATION, format!("Bearer {}", token)))
+    }
+
+    fn parse(&self, body: &str) -> Result<ChatResponse, AiCommitError> {
+        self.gemini.parse(body)
+    }
+
+    /// Access tokens last about an hour, so a 401 gets one retry with a
+    /// freshly minted token.
+    async fn complete(&self, req: &ChatRequest) -> Result<ChatResponse, AiCommitError> {
+        match execute(self, req).await {
+            Err(AiCommitError::ApiError { status: 401, .. }) => {
+                self.refresh_token()?;
+                match execute(self, req).await {
+                    Err(AiCommitError::ApiError {
+                        status: 401, body, ..
+                    }) => Err(AiCommitError::Credentials(format!(
+                        "Vertex AI rejected the access token: {}. Run `gcloud auth login` and try again.",
+                        body
+                    ))),
+                    other => other,
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+/// Mints an access token for the active gcloud account.
+fn gcloud_access_token() -> Result<String, AiCommitError> {
+    let output = Command::new("gcloud")
+        .args(["auth", "print-access-token"])
+        .output()
+        .map_err(|e| {
+            AiCommitError::Credentials(format!(
+                "No Vertex AI access token configured and gcloud could not be run ({}). \
+                Install the Google Cloud CLI or set an access token with `ai_commit config set-api-key`.",
+                e
+            ))
+        })?;
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || token.is_empty() {
+        return Err(AiCommitError::Credentials(format!(
+            "`gcloud auth print-access-token` failed: {}. Run `gcloud auth login` and try again.",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider() -> VertexProvider {
+        VertexProvider::new(&GenerationOptions {
+            url: "https://us-central1-aiplatform.googleapis.com/v1".to_string(),
+            project: Some("my-project".to_string()),
+            api_key: "token".to_string(),
+            ..GenerationOptions::default()
+        })
+    }
+
+    #[test]
+    fn request_uses_the_gemini_shape_at_the_publisher_model_endpoint() {
+        let req = GenerationOptions {
+            model: "gemini-1.5-flash".to_string(),
+            ..GenerationOptions::default()
+        }
+        .chat_request("diff --git a/x b/x\n");
+        let body = provider().body(&req);
+
+        assert_eq!(
+            body["systemInstruction"]["parts"][0]["text"],
+            json!(req.system)
+        );
+        assert_eq!(body["contents"][0]["role"], "user");
+        assert_eq!(body["contents"][0]["parts"][0]["text"], json!(req.user));
+        assert_eq!(
+            provider().endpoint(&req),
+            "https://us-central1-aiplatform.googleapis.com/v1/projects/my-project/locations/us-central1/publishers/google/models/gemini-1.5-flash:generateContent"
+        );
+    }
+
+    #[test]
+    fn response_gives_the_first_candidate() {
+        let response = provider()
+            .parse(
+                &json!({
+                    "candidates": [{
+                        "content": { "role": "model", "parts": [{ "text": "fix: close the file" }] },
+                        "finishReason": "MAX_TOKENS"
+                    }]
+                })
+                .to_string(),
+            )
+            .unwrap();
+        assert_eq!(response.content, "fix: close the file");
+        assert!(response.cut_off);
+
+        let empty = json!({ "candidates": [] }).to_string();
+        assert!(matches!(
+            provider().parse(&empty),
+            Err(AiCommitError::EmptyResponse)
+        ));
+    }
+}
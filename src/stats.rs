@@ -0,0 +1,139 @@
+// ===================================================================
+// Usage Statistics
+// ===================================================================
+
+use std::path::PathBuf;
+
+use rusqlite::{Connection, params};
+
+use crate::ai_commit::Generation;
+use crate::config::{Config, get_config_path};
+use crate::cost;
+use crate::error::AiCommitError;
+use crate::git::repo_root;
+
+/// How many recent messages `stats` lists.
+const RECENT: usize = 5;
+
+fn database_path() -> Result<PathBuf, AiCommitError> {
+    Ok(get_config_path()?.with_file_name("stats.db"))
+}
+
+fn open() -> Result<Connection, AiCommitError> {
+    let db = Connection::open(database_path()?)?;
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS generations (
+            id INTEGER PRIMARY KEY,
+            timestamp TEXT NOT NULL,
+            model TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            prompt_tokens INTEGER,
+            completion_tokens INTEGER,
+            language TEXT NOT NULL,
+            repo TEXT,
+            message TEXT NOT NULL
+        )",
+    )?;
+    Ok(db)
+}
+
+/// Records one successful generation.
+pub fn record(
+    generation: &Generation,
+    provider: &str,
+    language: &str,
+) -> Result<(), AiCommitError> {
+    let repo = repo_root().map(|root| root.display().to_string());
+    open()?.execute(
+        "INSERT INTO generations
+            (timestamp, model, provider, prompt_tokens, completion_tokens, language, repo, message)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            chrono::Utc::now().to_rfc3339(),
+            generation.model,
+            provider,
+            generation.usage.map(|usage| usage.prompt_tokens),
+            generation.usage.map(|usage| usage.completion_tokens),
+            language,
+            repo,
+            generation.message,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Prints call and token totals, an estimated cost per model, and the most
+/// recent messages.
+pub fn print_summary(config: &Config) -> Result<(), AiCommitError> {
+    let db = open()?;
+    let mut per_model = db.prepare(
+        "SELECT model, COUNT(*), COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0)
+         FROM generations GROUP BY model ORDER BY COUNT(*) DESC",
+    )?;
+    let rows = per_model
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, u64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    if rows.is_empty() {
+        println!("No generations recorded yet.");
+        return Ok(());
+    }
+
+    let calls: u64 = rows.iter().map(|row| row.1).sum();
+    let prompt: u64 = rows.iter().map(|row| row.2).sum();
+    let completion: u64 = rows.iter().map(|row| row.3).sum();
+    println!("Generations: {}", calls);
+    println!(
+        "Tokens: {} prompt + {} completion = {} total",
+        prompt,
+        completion,
+        prompt + completion
+    );
+    println!();
+    println!(
+        "{:<32} {:>6} {:>12} {:>10}",
+        "Model", "Calls", "Tokens", "Est. cost"
+    );
+    for (model, calls, prompt, completion) in &rows {
+        let cost = cost::price(model, config).map_or_else(
+            || "-".to_string(),
+            |price| cost::format(price.cost(*prompt, *completion)),
+        );
+        println!(
+            "{:<32} {:>6} {:>12} {:>10}",
+            model,
+            calls,
+            prompt + completion,
+            cost
+        );
+    }
+
+    let mut recent =
+        db.prepare("SELECT timestamp, message FROM generations ORDER BY id DESC LIMIT ?1")?;
+    let messages = recent
+        .query_map([RECENT], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    println!();
+    println!("Recent messages:");
+    for (timestamp, message) in messages {
+        let when = chrono::DateTime::parse_from_rfc3339(&timestamp)
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or(timestamp);
+        println!("  {}  {}", when, message.lines().next().unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Deletes every recorded generation.
+pub fn reset() -> Result<(), AiCommitError> {
+    open()?.execute("DELETE FROM generations", [])?;
+    Ok(())
+}
@@ -0,0 +1,198 @@
+// ===================================================================
+// Structured Commit Messages
+// ===================================================================
+
+use serde::Deserialize;
+use serde_json::json;
+
+/// Column the body is wrapped at, as `git log` readers expect.
+const BODY_WIDTH: usize = 72;
+
+/// Commit types the model may choose from.
+const TYPES: &[&str] = &[
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// Asks for the reply as JSON; only needed where the API can't be told
+/// through `response_format`, but harmless where it can.
+pub const INSTRUCTIONS: &str = " Reply with only a JSON object with the fields `type` \
+    (feat, fix, docs, style, refactor, perf, test, build, ci, chore or revert), `scope` \
+    (a short noun for the affected area, or null), `subject` (imperative, lowercase, no \
+    trailing period, at most 72 characters), `body` (what changed and why, or null) and \
+    `breaking` (true if the change breaks compatibility).";
+
+/// The parts of a Conventional Commits message, as the model returns them in
+/// structured mode.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommitParts {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub scope: Option<String>,
+    pub subject: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub breaking: bool,
+}
+
+impl CommitParts {
+    /// Parses the model's reply, allowing for a Markdown code fence around
+    /// it. Replies without a type or subject are rejected.
+    pub fn parse(content: &str) -> Option<CommitParts> {
+        let content = content.trim();
+        let content = content
+            .strip_prefix("```json")
+            .or_else(|| content.strip_prefix("```"))
+            .and_then(|rest| rest.trim_end().strip_suffix("```"))
+            .unwrap_or(content);
+        let parts: CommitParts = serde_json::from_str(content.trim()).ok()?;
+        (!parts.kind.trim().is_empty() && !parts.subject.trim().is_empty()).then_some(parts)
+    }
+
+    /// Assembles `type(scope)!: subject`, then the body wrapped at 72
+    /// columns after a blank line.
+    pub fn to_message(&self) -> String {
+        let scope = self
+            .scope
+            .as_deref()
+            .map(str::trim)
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| format!("({})", scope))
+            .unwrap_or_default();
+        let subject = self.subject.trim().trim_end_matches('.');
+        let mut message = format!(
+            "{}{}{}: {}",
+            self.kind.trim().to_lowercase(),
+            scope,
+            if self.breaking { "!" } else { "" },
+            subject
+        );
+        if let Some(body) = self.body.as_deref().map(str::trim)
+            && !body.is_empty()
+        {
+            message.push_str("\n\n");
+            message.push_str(&wrap(body, BODY_WIDTH));
+        }
+        message
+    }
+}
+
+/// The `response_format` for OpenAI-compatible APIs, constraining the reply
+/// to a `CommitParts` object.
+pub fn response_format() -> serde_json::Value {
+    json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": "commit_message",
+            "strict": true,
+            "schema": {
+                "type": "object",
+                "properties": {
+                    "type": { "type": "string", "enum": TYPES },
+                    "scope": { "type": ["string", "null"] },
+                    "subject": { "type": "string" },
+                    "body": { "type": ["string", "null"] },
+                    "breaking": { "type": "boolean" }
+                },
+                "required": ["type", "scope", "subject", "body", "breaking"],
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+/// Wraps each line of `text` at `width` columns. Blank lines are kept, and
+/// continuation lines of `- ` and `* ` list items are indented to match.
+fn wrap(text: &str, width: usize) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        let indent = if line.starts_with("- ") || line.starts_with("* ") {
+            "  "
+        } else {
+            ""
+        };
+        let mut current = String::new();
+        for word in line.split_whitespace() {
+            if !current.trim().is_empty()
+                && current.chars().count() + 1 + word.chars().count() > width
+            {
+                lines.push(std::mem::replace(&mut current, indent.to_string()));
+            }
+            if !current.trim().is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        lines.push(current);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accepts_a_reply_in_a_code_fence() {
+        let reply = "```json\n{\"type\": \"fix\", \"scope\": null, \"subject\": \"handle empty diffs\", \
+                     \"body\": null, \"breaking\": false}\n```\n";
+        let parts = CommitParts::parse(reply).unwrap();
+        assert_eq!(parts.kind, "fix");
+        assert_eq!(parts.subject, "handle empty diffs");
+        assert!(CommitParts::parse("```\n{\"type\": \"fix\", \"subject\": \"x\"}\n```").is_some());
+    }
+
+    #[test]
+    fn parse_rejects_an_empty_type_or_subject() {
+        assert!(CommitParts::parse("{\"type\": \" \", \"subject\": \"add a flag\"}").is_none());
+        assert!(CommitParts::parse("{\"type\": \"feat\", \"subject\": \"\"}").is_none());
+        assert!(CommitParts::parse("feat: add a flag").is_none());
+    }
+
+    #[test]
+    fn message_has_scope_breaking_mark_and_no_trailing_period() {
+        let parts = CommitParts {
+            kind: "Feat".to_string(),
+            scope: Some("cli".to_string()),
+            subject: "drop the --legacy flag.".to_string(),
+            body: None,
+            breaking: true,
+        };
+        assert_eq!(parts.to_message(), "feat(cli)!: drop the --legacy flag");
+
+        let parts = CommitParts {
+            scope: Some(" ".to_string()),
+            breaking: false,
+            body: Some("Explain why.".to_string()),
+            ..parts
+        };
+        assert_eq!(
+            parts.to_message(),
+            "feat: drop the --legacy flag\n\nExplain why."
+        );
+    }
+
+    #[test]
+    fn wrap_breaks_lines_at_the_width() {
+        let text = "word ".repeat(30);
+        let wrapped = wrap(&text, BODY_WIDTH);
+        assert!(wrapped.lines().count() > 1);
+        assert!(
+            wrapped
+                .lines()
+                .all(|line| line.chars().count() <= BODY_WIDTH)
+        );
+        assert_eq!(wrapped.split_whitespace().count(), 30);
+    }
+
+    #[test]
+    fn wrap_indents_list_item_continuations_and_keeps_blank_lines() {
+        let text = "Intro.\n\n- first item that goes on for long enough to need a second line of text\n* second";
+        assert_eq!(
+            wrap(text, 40),
+            "Intro.\n\n- first item that goes on for long\n  enough to need a second line of text\n* second"
+        );
+    }
+}
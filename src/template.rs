@@ -0,0 +1,106 @@
+// ===================================================================
+// Prompt Templates
+// ===================================================================
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::error::AiCommitError;
+
+/// The placeholders a prompt template may use.
+pub const PLACEHOLDERS: &[&str] = &["diff", "language", "branch", "files", "stat"];
+
+/// `{name}` with a lowercase name. Other braces, e.g. in a JSON example,
+/// are left alone.
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{([a-z_]+)\}").unwrap());
+
+/// Checks that `template` only uses supported placeholders, so a typo is
+/// reported before anything is sent.
+pub fn check(template: &str) -> Result<(), AiCommitError> {
+    let unknown: Vec<&str> = PLACEHOLDER
+        .captures_iter(template)
+        .map(|captures| captures.get(1).unwrap().as_str())
+        .filter(|name| !PLACEHOLDERS.contains(name))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(AiCommitError::Config(format!(
+        "Unknown placeholder(s) in prompt template: {}. Supported: {}",
+        unknown
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect::<Vec<_>>()
+            .join(", "),
+        PLACEHOLDERS
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )))
+}
+
+/// Replaces each `{name}` in `template` with its value in `values`.
+/// Placeholders without a value are kept as they are.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    PLACEHOLDER
+        .replace_all(template, |captures: &regex::Captures| {
+            let name = &captures[1];
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map_or_else(|| captures[0].to_string(), |(_, value)| value.to_string())
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_placeholders_pass_the_check() {
+        assert!(check("Write in {language} for {branch}:\n{stat}\n{files}\n{diff}").is_ok());
+        assert!(check("No placeholders at all.").is_ok());
+    }
+
+    #[test]
+    fn unknown_placeholders_are_named() {
+        let err = check("Summarise {dif} on {brnch}").unwrap_err().to_string();
+        assert!(err.contains("{dif}, {brnch}"));
+        assert!(err.contains("Supported: {diff}"));
+    }
+
+    #[test]
+    fn other_braces_are_not_placeholders() {
+        let template = r#"Reply as {"subject": "..."} or {Diff} or {{diff}}"#;
+        assert!(check(r#"Reply as {"subject": "..."} or {Diff}"#).is_ok());
+        assert_eq!(
+            render(template, &[("diff", "D")]),
+            r#"Reply as {"subject": "..."} or {Diff} or {D}"#
+        );
+    }
+
+    #[test]
+    fn render_fills_values_and_keeps_the_rest() {
+        assert_eq!(
+            render(
+                "{language}: {diff} ({branch})",
+                &[("diff", "+x"), ("language", "en")]
+            ),
+            "en: +x ({branch})"
+        );
+    }
+
+    #[test]
+    fn values_are_inserted_literally() {
+        // A diff that itself contains placeholders or `$` must not be
+        // expanded again.
+        let diff = "+let s = \"{language} $1 ${x}\";";
+        assert_eq!(
+            render("{diff}", &[("diff", diff), ("language", "en")]),
+            diff
+        );
+    }
+}
@@ -0,0 +1,137 @@
+// ===================================================================
+// Client-side Rate Limiting
+// ===================================================================
+
+use std::{
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::config::get_config_path;
+use crate::error::AiCommitError;
+use crate::progress;
+use crate::providers::format_wait;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Waits until a request fits within `per_minute` requests over the last
+/// minute, counting those sent by every ai_commit process. Send times are
+/// kept in a `throttle` file next to config.toml, locked while it is updated
+/// so that parallel runs take turns.
+pub async fn wait_for_slot(per_minute: u32) -> Result<(), AiCommitError> {
+    let wait = reserve_slot(per_minute)?;
+    if !wait.is_zero() {
+        progress::note(&format!(
+            "Waiting {} to stay within requests_per_minute ({}).",
+            format_wait(wait),
+            per_minute
+        ));
+        tokio::time::sleep(wait).await;
+    }
+    Ok(())
+}
+
+/// Records the next free send time and returns how long until then. The
+/// lock is only held while the file is read and rewritten, not while waiting.
+fn reserve_slot(per_minute: u32) -> Result<Duration, AiCommitError> {
+    let path = get_config_path()?.with_file_name("throttle");
+    let io_error = |e| {
+        AiCommitError::Config(format!(
+            "Failed to update the rate limit file {}: {}",
+            path.display(),
+            e
+        ))
+    };
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(io_error)?;
+    file.lock().map_err(io_error)?;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).map_err(io_error)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    // Millisecond send times; reservations may lie ahead.
+    let mut sent: Vec<Duration> = contents
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .map(Duration::from_millis)
+        .collect();
+    let send_at = next_send_time(&sent, now, per_minute);
+    sent.retain(|time| *time + WINDOW > now);
+    sent.push(send_at);
+    sent.sort();
+
+    let lines: String = sent
+        .iter()
+        .map(|time| format!("{}\n", time.as_millis()))
+        .collect();
+    file.set_len(0).map_err(io_error)?;
+    file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+    file.write_all(lines.as_bytes()).map_err(io_error)?;
+    file.unlock().map_err(io_error)?;
+    Ok(send_at - now)
+}
+
+/// When the next request may be sent, given earlier send times: `now` while
+/// fewer than `per_minute` were sent in the last minute, otherwise once the
+/// one `per_minute` back is a minute old.
+fn next_send_time(sent: &[Duration], now: Duration, per_minute: u32) -> Duration {
+    let mut recent: Vec<Duration> = sent
+        .iter()
+        .copied()
+        .filter(|time| *time + WINDOW > now)
+        .collect();
+    recent.sort();
+    let per_minute = per_minute.max(1) as usize;
+    if recent.len() < per_minute {
+        now
+    } else {
+        (recent[recent.len() - per_minute] + WINDOW).max(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: &[u64]) -> Vec<Duration> {
+        secs.iter().copied().map(Duration::from_secs).collect()
+    }
+
+    #[test]
+    fn requests_under_the_budget_go_now() {
+        let now = Duration::from_secs(1000);
+        assert_eq!(next_send_time(&[], now, 2), now);
+        assert_eq!(next_send_time(&secs(&[990]), now, 2), now);
+    }
+
+    #[test]
+    fn exhausted_budget_waits_for_the_oldest_counted_request_to_age_out() {
+        let now = Duration::from_secs(1000);
+        let sent = secs(&[990, 950, 980]);
+        assert_eq!(next_send_time(&sent, now, 2), Duration::from_secs(1040));
+        assert_eq!(next_send_time(&sent, now, 3), Duration::from_secs(1010));
+        // A reservation ahead of now counts like any other send.
+        assert_eq!(
+            next_send_time(&secs(&[1030]), now, 1),
+            Duration::from_secs(1090)
+        );
+    }
+
+    #[test]
+    fn requests_older_than_a_minute_no_longer_count() {
+        let now = Duration::from_secs(1000);
+        assert_eq!(next_send_time(&secs(&[900, 940]), now, 1), now);
+        assert_eq!(
+            next_send_time(&secs(&[900, 941]), now, 1),
+            Duration::from_secs(1001)
+        );
+    }
+}
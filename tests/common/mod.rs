@@ -0,0 +1,59 @@
+//! Runs the binary in scratch repositories with the offline `mock`
+//! provider.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+pub fn git(repo: &Path, args: &[&str]) -> Output {
+    Command::new("git")
+        .args(args)
+        .current_dir(repo)
+        .output()
+        .expect("git runs")
+}
+
+/// A repository with `files` staged and no commits.
+pub fn staged_repo(files: &[&str]) -> tempfile::TempDir {
+    let repo = tempfile::tempdir().unwrap();
+    git(repo.path(), &["init", "-q"]);
+    git(repo.path(), &["config", "user.email", "test@example.com"]);
+    git(repo.path(), &["config", "user.name", "Test"]);
+    for file in files {
+        std::fs::write(repo.path().join(file), format!("// {}\n", file)).unwrap();
+        git(repo.path(), &["add", file]);
+    }
+    repo
+}
+
+/// Runs `ai_commit generate` in `repo` with its own, empty config and cache.
+pub fn ai_commit(repo: &Path, args: &[&str]) -> Output {
+    ai_commit_command(repo, args)
+        .output()
+        .expect("ai_commit runs")
+}
+
+/// The command [`ai_commit`] runs, for tests that change its environment.
+pub fn ai_commit_command(repo: &Path, args: &[&str]) -> Command {
+    let home = repo.join(".home");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ai_commit"));
+    for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("AI_COMMIT_")) {
+        command.env_remove(name);
+    }
+    command
+        .arg("generate")
+        .args(args)
+        .current_dir(repo)
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        // Fails the run if an editor is opened when it shouldn't be.
+        .env("EDITOR", "ai-commit-test-missing-editor")
+        .env("VISUAL", "ai-commit-test-missing-editor");
+    command
+}
+
+pub fn has_commits(repo: &Path) -> bool {
+    git(repo, &["rev-parse", "--verify", "--quiet", "HEAD"])
+        .status
+        .success()
+}
@@ -0,0 +1,82 @@
+//! `--dry-run` and `--no-commit` print the message without running
+//! `git commit`.
+
+mod common;
+
+use common::{ai_commit, has_commits, staged_repo};
+
+#[test]
+fn dry_run_prints_the_message_and_does_not_commit() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit(repo.path(), &["--provider", "mock", "--dry-run"]);
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!String::from_utf8_lossy(&output.stdout).trim().is_empty());
+    assert!(!has_commits(repo.path()));
+}
+
+#[test]
+fn language_reaches_the_provider() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--dry-run", "--language", "de"],
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "[de] update 1 file: parser.rs"
+    );
+}
+
+#[test]
+fn no_commit_prints_the_reviewed_message_and_does_not_commit() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--no-commit", "--no-edit"],
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!String::from_utf8_lossy(&output.stdout).trim().is_empty());
+    assert!(!has_commits(repo.path()));
+}
+
+#[test]
+fn the_last_of_commit_and_no_commit_wins() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--no-edit", "--commit", "--no-commit"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!has_commits(repo.path()));
+
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--no-edit", "--no-commit", "--commit"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(has_commits(repo.path()));
+}
+
+#[test]
+fn dry_run_fails_when_the_api_does() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit(
+        repo.path(),
+        &[
+            "--provider",
+            "openai",
+            "--url",
+            "http://127.0.0.1:9/v1/chat/completions",
+            "--retries",
+            "0",
+            "--dry-run",
+        ],
+    );
+
+    assert!(!output.status.success());
+    assert!(!has_commits(repo.path()));
+}
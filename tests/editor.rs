@@ -0,0 +1,40 @@
+//! The message is committed once the editor exits successfully, and
+//! abandoned when it exits with an error. `--no-edit` skips the editor.
+
+mod common;
+
+use common::{ai_commit, ai_commit_command, has_commits, staged_repo};
+
+#[test]
+fn editor_that_exits_cleanly_commits_the_message() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit_command(repo.path(), &["--provider", "mock"])
+        .env("VISUAL", "true")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(has_commits(repo.path()));
+}
+
+#[test]
+fn editor_that_exits_with_an_error_aborts_the_commit() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit_command(repo.path(), &["--provider", "mock"])
+        .env("VISUAL", "false")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("commit aborted"));
+    assert!(!has_commits(repo.path()));
+}
+
+#[test]
+fn no_edit_commits_without_opening_the_editor() {
+    let repo = staged_repo(&["parser.rs"]);
+    let output = ai_commit(repo.path(), &["--provider", "mock", "--no-edit"]);
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(has_commits(repo.path()));
+}
@@ -0,0 +1,71 @@
+//! `--files` limits the diff and the commit to staged files matching the
+//! given pathspecs.
+
+mod common;
+
+use common::{ai_commit, git, has_commits, staged_repo};
+
+fn stdout(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn only_matching_files_are_described() {
+    let repo = staged_repo(&["parser.rs", "lexer.rs"]);
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--dry-run", "--files", "parser.rs"],
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(stdout(&output).contains("parser.rs"));
+    assert!(!stdout(&output).contains("lexer.rs"));
+}
+
+#[test]
+fn paths_that_match_no_staged_files_get_a_warning() {
+    let repo = staged_repo(&["parser.rs"]);
+    std::fs::write(repo.path().join("notes.txt"), "draft\n").unwrap();
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--dry-run", "--files", "notes.txt"],
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("--files notes.txt matches no staged files")
+    );
+    // Nothing is described, rather than everything that is staged.
+    assert!(!stdout(&output).contains("parser.rs"));
+    assert!(!has_commits(repo.path()));
+}
+
+#[test]
+fn matching_files_with_unstaged_changes_are_not_committed() {
+    let repo = staged_repo(&["parser.rs", "lexer.rs"]);
+    std::fs::write(repo.path().join("parser.rs"), "// not staged\n").unwrap();
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--no-edit", "--files", "parser.rs"],
+    );
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unstaged changes"));
+    assert!(!has_commits(repo.path()));
+}
+
+#[test]
+fn only_matching_files_are_committed() {
+    let repo = staged_repo(&["parser.rs", "lexer.rs"]);
+    let output = ai_commit(
+        repo.path(),
+        &["--provider", "mock", "--no-edit", "--files", "parser.rs"],
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    let committed = git(repo.path(), &["show", "--name-only", "--format=", "HEAD"]);
+    assert_eq!(stdout(&committed).trim(), "parser.rs");
+    let staged = git(repo.path(), &["diff", "--staged", "--name-only"]);
+    assert_eq!(stdout(&staged).trim(), "lexer.rs");
+}